@@ -1,18 +1,88 @@
 use std::{
-    env, fs,
+    fs,
     path::{Path, PathBuf},
 };
 
-use clap::Parser;
+use clap::{Parser, ValueEnum, error::ErrorKind};
 use directories::UserDirs;
 use ini::Ini;
 use rfd::FileDialog;
 
+use crate::{
+    EqPreset, OutputKind, ReplayGainMode, RepeatMode, Session, ShuffleMode, SleepMode, SortKey,
+};
+
+/// 配置文件路径
+const CONF_PATH: &str = "mini-conf.ini";
+
 #[derive(Parser, Debug)]
 pub struct Args {
     /// 音频目录
     #[arg(short, long)]
     pub dir: Option<PathBuf>,
+    /// 播放列表文件(`.m3u`/`.m3u8`/`.pls`/`.xspf`), 代替音频目录
+    #[arg(short, long, conflicts_with = "dir")]
+    pub playlist: Option<PathBuf>,
+    /// 音频输出后端, 没有声卡时可用`null`或`wav`
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: OutputKind,
+    /// `wav`输出后端写入的文件
+    #[arg(long, default_value = "mini-output.wav")]
+    pub wav_file: PathBuf,
+    /// 切歌时交叉淡化的秒数, 0为无缝衔接. 未指定时读取配置文件`[Playback] crossfade`
+    #[arg(short, long)]
+    pub crossfade: Option<f32>,
+    /// 响度归一化模式. 未指定时读取配置文件`[ReplayGain] mode`
+    #[arg(short, long, value_enum)]
+    pub replaygain: Option<ReplayGainMode>,
+    /// 响度归一化的前置放大(dB). 未指定时读取配置文件`[ReplayGain] preamp`
+    #[arg(long, allow_negative_numbers = true)]
+    pub preamp: Option<f32>,
+    /// 按峰值限制增益防止削波, 读取配置文件`[ReplayGain] prevent_clipping`, 默认开启
+    #[arg(skip)]
+    pub prevent_clipping: Option<bool>,
+    /// 音量(`0.0..=1.0`), 读取配置文件`[Playback] volume`, 退出时保存
+    #[arg(skip)]
+    pub volume: Option<f32>,
+    /// 是否静音, 读取配置文件`[Playback] muted`, 退出时保存
+    #[arg(skip)]
+    pub muted: Option<bool>,
+    /// 均衡器预设名称. 未指定时读取配置文件`[Equalizer] preset`
+    #[arg(short, long)]
+    pub eq: Option<String>,
+    /// 定时停止: `30`/`30m`分钟, `2h`小时, `current`播完当前曲目, `3t`当前曲目之后再播3首
+    #[arg(long, value_parser = SleepMode::parse)]
+    pub sleep: Option<SleepMode>,
+    /// 曲目信息的显示格式, 如`"[{artist} - ]{title}"`. 未指定时读取配置文件`[Display] format`
+    #[arg(long)]
+    pub format: Option<String>,
+    /// 歌词面板中当前行之前显示的行数, 默认2. 未指定时读取配置文件`[Display] lyrics_before`
+    #[arg(long)]
+    pub lyrics_before: Option<usize>,
+    /// 歌词面板中当前行之后显示的行数, 默认2. 未指定时读取配置文件`[Display] lyrics_after`
+    #[arg(long)]
+    pub lyrics_after: Option<usize>,
+    /// 播放列表排序方式. 未指定时读取配置文件`[Playback] sort`
+    #[arg(long, value_enum)]
+    pub sort: Option<SortKey>,
+    /// 随机播放模式, 默认`off`. 未指定时沿用上次的模式
+    #[arg(short, long, value_enum)]
+    pub shuffle: Option<ShuffleMode>,
+    /// 循环模式: `off`播完最后一首后停止, `all`(默认)列表循环, `one`单曲循环. 未指定时沿用上次的模式
+    #[arg(long, value_enum)]
+    pub repeat: Option<RepeatMode>,
+    /// 随机播放的种子, 相同种子得到相同的播放顺序
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// 忽略音乐库索引, 重新探测全部文件
+    #[arg(long)]
+    pub rescan: bool,
+    /// 不恢复上次的曲目、进度与待播队列, 从头播放
+    #[arg(long)]
+    pub no_resume: bool,
+    /// 配置文件`[EqPresets]`中的用户预设, 每项为`名称=10段增益(dB), 逗号分隔`
+    #[arg(skip)]
+    pub eq_presets: Vec<EqPreset>,
 }
 impl Default for Args {
     fn default() -> Self {
@@ -23,25 +93,61 @@ impl Default for Args {
 impl Args {
     /// 新建Args实例
     pub fn new() -> Self {
-        Self { dir: None }
+        Self {
+            dir: None,
+            playlist: None,
+            output: OutputKind::default(),
+            wav_file: PathBuf::from("mini-output.wav"),
+            crossfade: None,
+            replaygain: None,
+            preamp: None,
+            prevent_clipping: None,
+            volume: None,
+            muted: None,
+            eq: None,
+            sleep: None,
+            format: None,
+            lyrics_before: None,
+            lyrics_after: None,
+            sort: None,
+            shuffle: None,
+            repeat: None,
+            seed: None,
+            rescan: false,
+            no_resume: false,
+            eq_presets: Vec::new(),
+        }
     }
 
-    /// 无参数或解析失败时, 打开FileDialog选择目录. 无参数打开FileDialog还会保存路径到配置文件
+    /// 解析cmdline参数. 未指定目录时沿用上次会话的目录或播放列表, 再从配置文件加载,
+    /// 解析失败时打开FileDialog选择目录.
+    /// 配置文件未记录目录时打开FileDialog还会保存路径到配置文件. 最后加载其余播放设置
     pub fn get_dir(&mut self) {
-        if env::args().len() == 1 {
-            self.load_from_conf();
-        } else {
-            self.dir = match Args::try_parse() {
-                Ok(parse) => parse.dir,
-                Err(_) => match Args::open_dialog() {
+        match Args::try_parse() {
+            Ok(parse) => {
+                *self = parse;
+                if self.dir.is_none() && self.playlist.is_none() {
+                    self.load_from_session();
+                }
+                if self.dir.is_none() && self.playlist.is_none() {
+                    self.load_from_conf();
+                }
+            }
+            // `--help`/`--version`直接打印后退出
+            Err(e) if matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) => {
+                e.exit()
+            }
+            Err(_) => {
+                self.dir = match Args::open_dialog() {
                     Some(dir) => Some(dir),
                     none => {
                         println!("你没有选择任何目录");
                         none
                     }
-                },
-            };
+                };
+            }
         }
+        self.load_settings();
     }
     /// 打开FileDialog选择目录
     pub fn open_dialog() -> Option<PathBuf> {
@@ -63,51 +169,112 @@ impl Args {
         None
     }
 
+    /// 沿用上次会话的音乐目录或播放列表文件, 已不存在或指定了`--no-resume`时忽略
+    fn load_from_session(&mut self) {
+        let Some(session) = Session::load().filter(|_| !self.no_resume) else {
+            return;
+        };
+        self.dir = session.dir.filter(|dir| dir.is_dir());
+        self.playlist = session.playlist.filter(|file| file.is_file());
+    }
+
     /// 从配置文件加载路径
     fn load_from_conf(&mut self) {
-        let conf_path = "mini-conf.ini";
-        // 文件不存在就新建
-        if !Path::new(conf_path).exists()
-            && let Err(e) = fs::File::create(conf_path) {
-                eprintln!("创建文件失败: {}", e);
-                return;
-            }
-        let content = match fs::read_to_string(conf_path) {
-            Ok(c) => c,
-            Err(e) => {
-                eprintln!("读取配置失败: {e}");
-                return;
-            }
+        let Some(mut conf) = Args::load_conf() else {
+            return;
         };
-        let conf = Ini::new();
-        // 内容为空则打开FileDialog并保存路径到配置文件
-        if content.is_empty() {
-            match Args::open_dialog() {
+        match conf.get_from(Some("Directory"), "dir") {
+            // 读取本地配置
+            Some(dir) => self.dir = Some(PathBuf::from(dir)),
+            // 未配置目录则打开FileDialog并保存路径到配置文件
+            None => match Args::open_dialog() {
                 Some(dir) => {
+                    let dir_value = dir.to_string_lossy().into_owned();
+                    conf.with_section(Some("Directory")).set("dir", dir_value);
+                    Args::write_conf(&conf);
                     self.dir = Some(dir);
-                    let dir_value = self.dir.clone().unwrap().to_string_lossy().into_owned();
-                    Args::write_conf(conf, conf_path, dir_value);
                 }
                 _none => {
                     println!("你没有选择任何目录");
                 }
+            },
+        }
+    }
+
+    /// 从配置文件加载播放设置, 命令行参数优先
+    fn load_settings(&mut self) {
+        let Some(conf) = Args::load_conf() else {
+            return;
+        };
+        let get = |key: &str| conf.get_from(Some("Playback"), key);
+        if self.crossfade.is_none() {
+            self.crossfade = get("crossfade").and_then(|v| v.parse().ok());
+        }
+        if self.sort.is_none() {
+            self.sort = get("sort").and_then(|v| SortKey::from_str(v, true).ok());
+        }
+        self.volume = get("volume").and_then(|v| v.parse().ok());
+        self.muted = get("muted").and_then(|v| v.parse().ok());
+        let get = |key: &str| conf.get_from(Some("ReplayGain"), key);
+        if self.replaygain.is_none() {
+            self.replaygain = get("mode").and_then(|v| ReplayGainMode::from_str(v, true).ok());
+        }
+        if self.preamp.is_none() {
+            self.preamp = get("preamp").and_then(|v| v.parse().ok());
+        }
+        if self.prevent_clipping.is_none() {
+            self.prevent_clipping = get("prevent_clipping").and_then(|v| v.parse().ok());
+        }
+        if self.eq.is_none() {
+            self.eq = conf.get_from(Some("Equalizer"), "preset").map(str::to_string);
+        }
+        if self.format.is_none() {
+            self.format = conf.get_from(Some("Display"), "format").map(str::to_string);
+        }
+        let get = |key: &str| conf.get_from(Some("Display"), key);
+        if self.lyrics_before.is_none() {
+            self.lyrics_before = get("lyrics_before").and_then(|v| v.parse().ok());
+        }
+        if self.lyrics_after.is_none() {
+            self.lyrics_after = get("lyrics_after").and_then(|v| v.parse().ok());
+        }
+        if let Some(section) = conf.section(Some("EqPresets")) {
+            self.eq_presets = section
+                .iter()
+                .filter_map(|(name, value)| EqPreset::parse(name, value))
+                .collect();
+        }
+    }
+
+    /// 读取配置文件, 文件不存在就新建
+    fn load_conf() -> Option<Ini> {
+        if !Path::new(CONF_PATH).exists()
+            && let Err(e) = fs::File::create(CONF_PATH)
+        {
+            eprintln!("创建文件失败: {}", e);
+            return None;
+        }
+        match Ini::load_from_file(CONF_PATH) {
+            Ok(conf) => Some(conf),
+            Err(e) => {
+                eprintln!("读取配置失败: {e}");
+                None
             }
-        } else {
-            // 否则读取本地配置
-            let mut dir_buf = PathBuf::new();
-            let dir = Args::read_conf(content);
-            dir_buf.push(dir);
-            self.dir = Some(dir_buf);
         }
     }
-    fn read_conf(content: String) -> String {
-        let conf = Ini::load_from_str(&content).unwrap();
-        let section = conf.section(Some("Directory")).unwrap();
-        let dir_value = section.get("dir").unwrap();
-        dir_value.to_string()
+    /// 保存一组设置到配置文件的指定节, 保留其余内容
+    pub fn save_settings(section: &str, values: &[(&str, String)]) {
+        let Some(mut conf) = Args::load_conf() else {
+            return;
+        };
+        for (key, value) in values {
+            conf.with_section(Some(section)).set(*key, value.as_str());
+        }
+        Args::write_conf(&conf);
     }
-    fn write_conf(mut conf: Ini, conf_path: &str, dir_value: String) {
-        conf.with_section(Some("Directory")).set("dir", dir_value);
-        conf.write_to_file(conf_path).unwrap();
+    fn write_conf(conf: &Ini) {
+        if let Err(e) = conf.write_to_file(CONF_PATH) {
+            eprintln!("保存配置失败: {e}");
+        }
     }
 }
@@ -0,0 +1,74 @@
+use std::f64::consts::PI;
+
+/// 二阶IIR滤波器(直接II型转置), 系数已按`a0`归一化
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    /// RBJ峰值滤波器: 以`freq`为中心提升/衰减`gain_db`
+    pub fn peaking(sample_rate: f64, freq: f64, q: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Self::new(
+            [
+                (1.0 + alpha * a) / a0,
+                -2.0 * cos / a0,
+                (1.0 - alpha * a) / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha / a) / a0],
+        )
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 正弦波经过滤波器后的稳态幅度
+    fn sine_gain(filter: &mut Biquad, sample_rate: f64, freq: f64) -> f64 {
+        let len = sample_rate as usize;
+        let out: Vec<f64> = (0..len)
+            .map(|i| filter.process((2.0 * PI * freq * i as f64 / sample_rate).sin()))
+            .collect();
+        out[len / 2..].iter().fold(0.0, |max, y| max.max(y.abs()))
+    }
+
+    #[test]
+    fn zero_gain_is_identity() {
+        let filter = Biquad::peaking(48000.0, 1000.0, 1.41, 0.0);
+        assert!((filter.b[0] - 1.0).abs() < 1e-12);
+        assert!((filter.b[1] - filter.a[0]).abs() < 1e-12);
+        assert!((filter.b[2] - filter.a[1]).abs() < 1e-12);
+    }
+
+    #[test]
+    fn peaking_boosts_only_around_center() {
+        let mut filter = Biquad::peaking(48000.0, 1000.0, 1.41, 6.0);
+        let center = 20.0 * sine_gain(&mut filter, 48000.0, 1000.0).log10();
+        assert!((center - 6.0).abs() < 0.1, "{center}");
+        let mut filter = Biquad::peaking(48000.0, 1000.0, 1.41, 6.0);
+        let far = 20.0 * sine_gain(&mut filter, 48000.0, 50.0).log10();
+        assert!(far.abs() < 0.2, "{far}");
+        let mut filter = Biquad::peaking(48000.0, 1000.0, 1.41, -6.0);
+        let cut = 20.0 * sine_gain(&mut filter, 48000.0, 1000.0).log10();
+        assert!((cut + 6.0).abs() < 0.1, "{cut}");
+    }
+}
@@ -0,0 +1,268 @@
+use super::biquad::Biquad;
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// 各频段的中心频率(Hz)
+pub const EQ_BANDS: [f64; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// 频段的Q值, 约为一个倍频程的带宽
+const BAND_Q: f64 = 1.41;
+/// 每隔多少帧检查一次设置和采样率的变化
+const CHECK_INTERVAL: u32 = 512;
+
+/// 均衡器预设, 各频段增益单位为dB
+#[derive(Clone, Debug, PartialEq)]
+pub struct EqPreset {
+    pub name: String,
+    pub gains: [f32; 10],
+}
+
+impl EqPreset {
+    pub fn new(name: &str, gains: [f32; 10]) -> Self {
+        Self {
+            name: name.to_string(),
+            gains,
+        }
+    }
+
+    /// 内置预设: flat, rock, vocal, bass boost
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("flat", [0.0; 10]),
+            Self::new("rock", [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0]),
+            Self::new("vocal", [-3.0, -2.0, -1.0, 1.0, 3.0, 4.0, 4.0, 3.0, 1.0, 0.0]),
+            Self::new("bass boost", [7.0, 6.0, 5.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        ]
+    }
+
+    /// 解析形如`4,3,1,0,-1,0,1,2,3,3`的10段增益
+    pub fn parse(name: &str, value: &str) -> Option<Self> {
+        let gains: Vec<f32> = value
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        Some(Self::new(name, gains.try_into().ok()?))
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.gains.iter().all(|g| *g == 0.0)
+    }
+}
+
+/// 可在播放中切换的均衡器设置
+#[derive(Clone, Debug, Default)]
+pub struct EqControl {
+    /// 设置版本号, 每次修改加1
+    version: Arc<AtomicU64>,
+    gains: Arc<Mutex<[f32; 10]>>,
+}
+
+impl EqControl {
+    pub fn set(&self, gains: [f32; 10]) {
+        *self.gains.lock().unwrap() = gains;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+    fn gains(&self) -> [f32; 10] {
+        *self.gains.lock().unwrap()
+    }
+}
+
+/// 10段峰值滤波均衡器
+///
+/// 设置、采样率或声道数改变时重新计算滤波器系数, 全部增益为0时直通
+pub struct Equalizer<S> {
+    inner: S,
+    control: EqControl,
+    /// 当前系数对应的设置版本、采样率、声道数
+    version: u64,
+    sample_rate: SampleRate,
+    channels: usize,
+    /// 每个声道的各频段滤波器
+    filters: Vec<Vec<Biquad>>,
+    /// 防止提升频段削波的前置衰减
+    headroom: f64,
+    /// 当前帧的声道, 以及距下次检查的帧数
+    channel: usize,
+    countdown: u32,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(inner: S, control: EqControl) -> Self {
+        let mut eq = Self {
+            inner,
+            control,
+            version: 0,
+            sample_rate: 0,
+            channels: 0,
+            filters: Vec::new(),
+            headroom: 1.0,
+            channel: 0,
+            countdown: 0,
+        };
+        eq.update();
+        eq
+    }
+
+    /// 按当前设置和采样率重新计算滤波器
+    fn update(&mut self) {
+        self.version = self.control.version();
+        self.sample_rate = self.inner.sample_rate().max(1);
+        self.channels = self.inner.channels().max(1) as usize;
+        let gains = self.control.gains();
+        let fs = self.sample_rate as f64;
+        // 跳过增益为0以及超过奈奎斯特频率的频段
+        let bands: Vec<Biquad> = EQ_BANDS
+            .iter()
+            .zip(gains)
+            .filter(|(freq, gain)| *gain != 0.0 && **freq < fs * 0.45)
+            .map(|(freq, gain)| Biquad::peaking(fs, *freq, BAND_Q, gain as f64))
+            .collect();
+        let boost = gains.iter().fold(0.0f32, |max, g| max.max(*g)) as f64;
+        self.headroom = 10f64.powf(-boost / 20.0);
+        self.filters = vec![bands; self.channels];
+    }
+
+    /// 在帧边界检查是否需要重新计算系数
+    fn check(&mut self) {
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return;
+        }
+        self.countdown = CHECK_INTERVAL;
+        if self.version != self.control.version()
+            || self.sample_rate != self.inner.sample_rate()
+            || self.channels != self.inner.channels() as usize
+        {
+            self.update();
+        }
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.check();
+        }
+        let sample = self.inner.next()?;
+        let filters = &mut self.filters[self.channel];
+        self.channel = (self.channel + 1) % self.channels;
+        if filters.is_empty() {
+            return Some(sample);
+        }
+        let y = filters
+            .iter_mut()
+            .fold(sample as f64 * self.headroom, |x, f| f.process(x));
+        Some(y as f32)
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::f32::consts::PI;
+
+    /// 1秒的正弦波经过均衡器后的稳态幅度
+    fn sine_through(gains: [f32; 10], freq: f32) -> f32 {
+        let rate = 48000;
+        let samples: Vec<f32> = (0..rate)
+            .map(|i| 0.1 * (2.0 * PI * freq * i as f32 / rate as f32).sin())
+            .collect();
+        let control = EqControl::default();
+        control.set(gains);
+        let out: Vec<f32> = Equalizer::new(SamplesBuffer::new(1, rate, samples), control).collect();
+        out[rate as usize / 2..]
+            .iter()
+            .fold(0.0, |max, y| max.max(y.abs()))
+    }
+
+    #[test]
+    fn flat_preset_passes_through() {
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.37).sin()).collect();
+        let control = EqControl::default();
+        control.set(EqPreset::builtin()[0].gains);
+        let eq = Equalizer::new(SamplesBuffer::new(2, 44100, samples.clone()), control);
+        assert_eq!(eq.collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn boosted_band_raises_its_center_frequency() {
+        let mut gains = [0.0; 10];
+        gains[5] = 6.0;
+        // 整体先衰减6dB留出余量, 中心频率处提升后回到原幅度, 远处的频率只被衰减
+        let center = sine_through(gains, 1000.0);
+        let far = sine_through(gains, 62.0);
+        assert!((center - 0.1).abs() < 0.002, "{center}");
+        assert!((far - 0.05).abs() < 0.002, "{far}");
+        assert!((20.0 * (center / far).log10() - 6.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn headroom_follows_the_largest_boost() {
+        let control = EqControl::default();
+        control.set([3.0, -12.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 12.0]);
+        let eq = Equalizer::new(SamplesBuffer::new(1, 48000, vec![0.0; 10]), control.clone());
+        assert!((eq.headroom - 10f64.powf(-12.0 / 20.0)).abs() < 1e-9);
+        // 只有衰减时不需要余量
+        control.set([-6.0; 10]);
+        let eq = Equalizer::new(SamplesBuffer::new(1, 48000, vec![0.0; 10]), control);
+        assert_eq!(eq.headroom, 1.0);
+    }
+
+    #[test]
+    fn bands_above_nyquist_are_skipped() {
+        let control = EqControl::default();
+        control.set([1.0; 10]);
+        let eq = Equalizer::new(SamplesBuffer::new(2, 22050, vec![0.0; 10]), control);
+        assert_eq!(eq.filters.len(), 2);
+        // 16kHz超过22.05kHz采样率的奈奎斯特频率附近, 不参与滤波
+        assert_eq!(eq.filters[0].len(), 9);
+    }
+
+    #[test]
+    fn preset_parsing() {
+        let preset = EqPreset::parse("mine", "4, 3,1,0,-1,0,1,2,3,3.5").unwrap();
+        assert_eq!(
+            preset.gains,
+            [4.0, 3.0, 1.0, 0.0, -1.0, 0.0, 1.0, 2.0, 3.0, 3.5]
+        );
+        assert!(!preset.is_flat());
+        assert_eq!(EqPreset::parse("short", "1,2,3"), None);
+        assert_eq!(EqPreset::parse("bad", "1,2,3,4,5,6,7,8,9,x"), None);
+        assert!(
+            EqPreset::parse("flat", "0,0,0,0,0,0,0,0,0,0")
+                .unwrap()
+                .is_flat()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    f32::consts::FRAC_PI_2,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+/// 等功率淡入淡出
+///
+/// 开头按`sin`曲线淡入, 收到淡出请求后按`cos`曲线淡出, 淡出结束即结束音源.
+/// 两个音源交叉时总功率保持不变
+pub struct Fader<S> {
+    inner: S,
+    /// 淡入/淡出的样本数(含所有声道)
+    fade_len: u64,
+    /// 是否淡入
+    fade_in: bool,
+    /// 已输出的样本数
+    played: u64,
+    /// 淡出请求
+    fade_out: Arc<AtomicBool>,
+    /// 淡出已进行的样本数
+    fading: Option<u64>,
+}
+
+impl<S: Source> Fader<S> {
+    /// 包装音源, 返回实例及其淡出开关
+    pub fn new(inner: S, fade_len: Duration, fade_in: bool) -> (Self, Arc<AtomicBool>) {
+        let samples_per_sec = inner.sample_rate() as f64 * inner.channels() as f64;
+        let fade_len = (fade_len.as_secs_f64() * samples_per_sec) as u64;
+        let fade_out = Arc::new(AtomicBool::new(false));
+        let fader = Self {
+            inner,
+            fade_len,
+            fade_in,
+            played: 0,
+            fade_out: Arc::clone(&fade_out),
+            fading: None,
+        };
+        (fader, fade_out)
+    }
+
+    /// 当前样本的增益
+    fn gain(&mut self) -> Option<f32> {
+        if self.fade_len == 0 {
+            // 没有淡化时长, 淡出即立刻停止
+            return match self.fade_out.load(Ordering::Relaxed) {
+                true => None,
+                false => Some(1.0),
+            };
+        }
+        let mut gain = 1.0;
+        if self.fade_in && self.played < self.fade_len {
+            gain *= (self.played as f32 / self.fade_len as f32 * FRAC_PI_2).sin();
+        }
+        if self.fading.is_none() && self.fade_out.load(Ordering::Relaxed) {
+            self.fading = Some(0);
+        }
+        if let Some(fading) = self.fading.as_mut() {
+            if *fading >= self.fade_len {
+                return None;
+            }
+            gain *= (*fading as f32 / self.fade_len as f32 * FRAC_PI_2).cos();
+            *fading += 1;
+        }
+        Some(gain)
+    }
+}
+
+impl<S: Source> Iterator for Fader<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let gain = self.gain()?;
+        let sample = self.inner.next()?;
+        self.played += 1;
+        Some(sample * gain)
+    }
+}
+
+impl<S: Source> Source for Fader<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// 100Hz单声道, 全为1的音源
+    fn ones(secs: usize) -> SamplesBuffer {
+        SamplesBuffer::new(1, 100, vec![1.0; secs * 100])
+    }
+
+    #[test]
+    fn crossfade_keeps_power_constant() {
+        let (fade_in, _) = Fader::new(ones(3), Duration::from_secs(1), true);
+        let (fade_out, stop) = Fader::new(ones(3), Duration::from_secs(1), false);
+        stop.store(true, Ordering::Relaxed);
+        let rising: Vec<f32> = fade_in.take(100).collect();
+        let falling: Vec<f32> = fade_out.collect();
+        // 淡出结束即结束音源
+        assert_eq!(falling.len(), 100);
+        assert_eq!((rising[0], falling[0]), (0.0, 1.0));
+        // 中点两者都是-3dB
+        assert!((rising[50] - 0.5f32.sqrt()).abs() < 1e-4);
+        assert!((falling[50] - 0.5f32.sqrt()).abs() < 1e-4);
+        for (a, b) in rising.iter().zip(&falling) {
+            assert!((a * a + b * b - 1.0).abs() < 1e-4, "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn full_volume_after_fade_in() {
+        let (fader, _) = Fader::new(ones(2), Duration::from_secs(1), true);
+        let samples: Vec<f32> = fader.collect();
+        assert_eq!(samples.len(), 200);
+        assert!(samples[100..].iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn zero_length_stops_at_once() {
+        let (mut fader, stop) = Fader::new(ones(1), Duration::ZERO, true);
+        assert_eq!(fader.next(), Some(1.0));
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(fader.next(), None);
+    }
+}
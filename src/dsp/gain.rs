@@ -0,0 +1,76 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+/// 增益平滑系数, 增益改变时按指数曲线过渡到新值, 避免爆音.
+/// 时间常数为2000个样本, 44.1kHz立体声约23ms
+const SMOOTHING: f32 = 0.0005;
+
+/// 可在播放中调整的增益控制, 以`f32`的位模式存放线性增益
+#[derive(Clone, Debug)]
+pub struct GainControl(Arc<AtomicU32>);
+
+impl GainControl {
+    pub fn new(linear: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(linear.to_bits())))
+    }
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set(&self, linear: f32) {
+        self.0.store(linear.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// 按`GainControl`放大/衰减音源
+pub struct Gain<S> {
+    inner: S,
+    control: GainControl,
+    /// 当前实际使用的增益, 平滑趋近目标值
+    current: f32,
+}
+
+impl<S: Source> Gain<S> {
+    pub fn new(inner: S, control: GainControl) -> Self {
+        let current = control.get();
+        Self {
+            inner,
+            control,
+            current,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Gain<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        let target = self.control.get();
+        self.current += (target - self.current) * SMOOTHING;
+        Some(sample * self.current)
+    }
+}
+
+impl<S: Source> Source for Gain<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
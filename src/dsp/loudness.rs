@@ -0,0 +1,170 @@
+use super::biquad::Biquad;
+use std::f64::consts::PI;
+
+/// 绝对门限(LUFS)
+const ABSOLUTE_GATE: f64 = -70.0;
+/// 相对门限(LU)
+const RELATIVE_GATE: f64 = -10.0;
+
+/// EBU R128 / ITU-R BS.1770 响度测量
+///
+/// 按K加权滤波后, 以400ms块(75%重叠)做绝对与相对门限, 得到综合响度
+pub struct LoudnessMeter {
+    channels: usize,
+    /// 每个声道的两级K加权滤波器
+    filters: Vec<[Biquad; 2]>,
+    /// 每个声道的加权系数
+    weights: Vec<f64>,
+    /// 100ms子块的样本帧数
+    step_frames: usize,
+    /// 当前子块已累积的帧数与能量
+    frames: usize,
+    energy: f64,
+    /// 已完成子块的平均能量
+    steps: Vec<f64>,
+    /// 采样峰值
+    peak: f32,
+    /// 当前帧已处理的声道
+    channel: usize,
+    frame_energy: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        let fs = sample_rate.max(1) as f64;
+        // 第一级: 高架滤波, 模拟头部声学效应
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+        // 第二级: RLB高通滤波
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+        // 5声道以上按5.1排列: 低音声道不计, 环绕声道加权1.41
+        let weights = (0..channels)
+            .map(|ch| match (channels >= 5, ch) {
+                (true, 3) => 0.0,
+                (true, ch) if ch >= 4 => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        Self {
+            channels,
+            filters: vec![[shelf, highpass]; channels],
+            weights,
+            step_frames: (fs / 10.0).round() as usize,
+            frames: 0,
+            energy: 0.0,
+            steps: Vec::new(),
+            peak: 0.0,
+            channel: 0,
+            frame_energy: 0.0,
+        }
+    }
+
+    /// 输入一个交错排列的样本
+    pub fn push(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        let ch = self.channel;
+        let [shelf, highpass] = &mut self.filters[ch];
+        let y = highpass.process(shelf.process(sample as f64));
+        self.frame_energy += self.weights[ch] * y * y;
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.energy += self.frame_energy;
+            self.frame_energy = 0.0;
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.energy / self.frames as f64);
+                self.frames = 0;
+                self.energy = 0.0;
+            }
+        }
+    }
+
+    /// 采样峰值(线性)
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// 综合响度(LUFS), 有效内容不足400ms时返回`None`
+    pub fn integrated(&self) -> Option<f64> {
+        // 每4个100ms子块组成一个400ms块
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / 4.0)
+            .filter(|&power| loudness(power) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let mean = blocks.iter().sum::<f64>() / blocks.len() as f64;
+        let gate = loudness(mean) + RELATIVE_GATE;
+        let gated: Vec<f64> = blocks.into_iter().filter(|&p| loudness(p) > gate).collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+}
+
+/// 均方能量换算为响度
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `secs`秒的1kHz正弦波, 各声道相同
+    fn measure_sine(amplitude: f32, channels: u16, secs: f32) -> LoudnessMeter {
+        let rate = 48000;
+        let mut meter = LoudnessMeter::new(channels, rate);
+        for i in 0..(secs * rate as f32) as usize {
+            let sample = amplitude * (2.0 * PI * 1000.0 * i as f64 / rate as f64).sin() as f32;
+            for _ in 0..channels {
+                meter.push(sample);
+            }
+        }
+        meter
+    }
+
+    #[test]
+    fn sine_loudness() {
+        // 单声道满幅1kHz正弦波为-3.01 LUFS, 两个声道相同时为0 LUFS
+        let mono = measure_sine(0.1, 1, 3.0).integrated().unwrap();
+        assert!((mono + 23.01).abs() < 0.1, "{mono}");
+        let stereo = measure_sine(0.1, 2, 3.0).integrated().unwrap();
+        assert!((stereo + 20.0).abs() < 0.1, "{stereo}");
+    }
+
+    #[test]
+    fn peak_is_the_largest_sample() {
+        let meter = measure_sine(0.5, 2, 1.0);
+        assert!((meter.peak() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn silence_and_short_input_have_no_loudness() {
+        assert_eq!(measure_sine(0.0, 2, 3.0).integrated(), None);
+        assert_eq!(measure_sine(0.5, 2, 0.3).integrated(), None);
+    }
+}
@@ -0,0 +1,13 @@
+mod biquad;
+mod eq;
+mod fade;
+mod gain;
+mod loudness;
+mod segment;
+mod stretch;
+pub use eq::*;
+pub use fade::*;
+pub use gain::*;
+pub use loudness::*;
+pub use segment::*;
+pub use stretch::*;
@@ -0,0 +1,140 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::time::Duration;
+
+/// 只播放音源中的一段, 用于CUE表拆分出的曲目
+///
+/// 开始时定位到起点, 到达终点即结束. 对外的位置与时长都相对于起点
+pub struct Segment<S> {
+    inner: S,
+    /// 起点
+    start: Duration,
+    /// 长度, `None`表示播放到音源结束
+    len: Option<Duration>,
+    /// 到终点前剩余的样本数(含所有声道)
+    remaining: Option<u64>,
+}
+
+impl<S: Source> Segment<S> {
+    /// 截取`start..end`, `end`为`None`时截取到结尾
+    ///
+    /// 音源不支持定位时, 逐个丢弃起点之前的样本
+    pub fn new(mut inner: S, start: Duration, end: Option<Duration>) -> Self {
+        if !start.is_zero() && inner.try_seek(start).is_err() {
+            let skip = samples(&inner, start);
+            inner.by_ref().take(skip as usize).for_each(drop);
+        }
+        let len = end.map(|end| end.saturating_sub(start));
+        let remaining = len.map(|len| samples(&inner, len));
+        Self {
+            inner,
+            start,
+            len,
+            remaining,
+        }
+    }
+}
+
+/// 时长换算为样本数, 按整帧计
+fn samples<S: Source>(source: &S, duration: Duration) -> u64 {
+    let frames = (duration.as_secs_f64() * source.sample_rate() as f64) as u64;
+    frames * source.channels() as u64
+}
+
+impl<S: Source> Iterator for Segment<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        match &mut self.remaining {
+            Some(0) => None,
+            Some(remaining) => {
+                *remaining -= 1;
+                self.inner.next()
+            }
+            None => self.inner.next(),
+        }
+    }
+}
+
+impl<S: Source> Source for Segment<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        match (self.inner.current_span_len(), self.remaining) {
+            (Some(len), Some(remaining)) => Some(len.min(remaining as usize)),
+            (len, _) => len,
+        }
+    }
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.len.or_else(|| {
+            self.inner
+                .total_duration()
+                .map(|total| total.saturating_sub(self.start))
+        })
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let pos = self.len.map_or(pos, |len| pos.min(len));
+        self.inner.try_seek(self.start + pos)?;
+        self.remaining = self.len.map(|len| samples(&self.inner, len - pos));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// 每秒100帧、共10秒的音源, 样本值为帧的序号
+    fn ramp(channels: u16) -> SamplesBuffer {
+        let samples: Vec<f32> = (0..1000)
+            .flat_map(|i| std::iter::repeat_n(i as f32, channels as usize))
+            .collect();
+        SamplesBuffer::new(channels, 100, samples)
+    }
+
+    #[test]
+    fn plays_only_the_range() {
+        let segment = Segment::new(
+            ramp(1),
+            Duration::from_secs(2),
+            Some(Duration::from_secs(5)),
+        );
+        assert_eq!(segment.total_duration(), Some(Duration::from_secs(3)));
+        let samples: Vec<f32> = segment.collect();
+        assert_eq!(samples.len(), 300);
+        assert_eq!(samples.first(), Some(&200.0));
+        assert_eq!(samples.last(), Some(&499.0));
+    }
+
+    #[test]
+    fn open_end_runs_to_source_end() {
+        let segment = Segment::new(ramp(2), Duration::from_secs(8), None);
+        assert_eq!(segment.total_duration(), Some(Duration::from_secs(2)));
+        // 按帧计数, 每帧两个声道
+        assert_eq!(segment.count(), 400);
+    }
+
+    #[test]
+    fn seek_is_relative_to_start() {
+        let mut segment = Segment::new(
+            ramp(1),
+            Duration::from_secs(2),
+            Some(Duration::from_secs(5)),
+        );
+        segment.try_seek(Duration::from_secs(1)).unwrap();
+        assert_eq!(segment.next(), Some(300.0));
+        assert_eq!(segment.count(), 199);
+        // 超出终点时停在终点
+        let mut segment = Segment::new(
+            ramp(1),
+            Duration::from_secs(2),
+            Some(Duration::from_secs(5)),
+        );
+        segment.try_seek(Duration::from_secs(9)).unwrap();
+        assert_eq!(segment.next(), None);
+    }
+}
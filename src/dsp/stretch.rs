@@ -0,0 +1,373 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// 最低播放速度
+pub const MIN_SPEED: f32 = 0.5;
+/// 最高播放速度
+pub const MAX_SPEED: f32 = 3.0;
+/// 分析帧长
+const FRAME: Duration = Duration::from_millis(40);
+/// 相似度搜索范围(±)
+const TOLERANCE: Duration = Duration::from_millis(8);
+/// 粗搜索的步长(帧)
+const COARSE_STEP: usize = 4;
+
+/// 可在播放中调整的播放速度, 以`f32`的位模式存放
+#[derive(Clone, Debug)]
+pub struct SpeedControl(Arc<AtomicU32>);
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl SpeedControl {
+    pub fn new(speed: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(speed.to_bits())))
+    }
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set(&self, speed: f32) {
+        self.0.store(speed.clamp(MIN_SPEED, MAX_SPEED).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// 媒体时钟: 正在输出的样本在原音频中的位置, 不受播放速度影响
+#[derive(Clone, Debug, Default)]
+pub struct MediaClock(Arc<AtomicU64>);
+
+impl MediaClock {
+    pub fn get(&self) -> Duration {
+        Duration::from_micros(self.0.load(Ordering::Relaxed))
+    }
+    fn set(&self, pos: Duration) {
+        self.0.store(pos.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// WSOLA(波形相似重叠相加)变速不变调
+///
+/// 以`速度 × 合成步长`的间隔从输入取帧, 在搜索范围内挑选与上一帧自然延续最相似的位置,
+/// 加汉宁窗后按固定合成步长重叠相加. 速度为1.0且尚未变速时直通输入
+///
+/// 声道数与采样率只在创建时读取一次, 要求音源全程格式不变. 播放器的音源都由单个文件
+/// 解码而来, 满足这一点; 中途改变格式的音源(如串接的Ogg流)需按段分别包装
+pub struct TimeStretch<S> {
+    inner: S,
+    speed: SpeedControl,
+    clock: MediaClock,
+    channels: usize,
+    sample_rate: u32,
+    /// 帧长、合成步长、搜索范围(帧)
+    frame_len: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    /// 最近一次定位的位置, 帧号均从此处起算
+    base: Duration,
+    /// 已从输入读取的样本数
+    consumed: u64,
+    /// 是否已进入变速处理
+    stretching: bool,
+    /// 输入缓冲(交错样本)及其首帧的帧号
+    input: Vec<f32>,
+    input_start: u64,
+    input_done: bool,
+    /// 下一帧的分析位置(帧号)
+    analysis: f64,
+    /// 上一帧选取的起点(帧号)
+    prev: Option<u64>,
+    /// 重叠相加缓冲, 长度为一帧
+    overlap: Vec<f32>,
+    /// 已合成、待输出的样本
+    ready: VecDeque<f32>,
+    finished: bool,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(inner: S, speed: SpeedControl) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate().max(1);
+        let frames = |d: Duration| (d.as_secs_f64() * sample_rate as f64) as usize;
+        let hop = (frames(FRAME) / 2).max(1);
+        let frame_len = hop * 2;
+        // 周期汉宁窗, 50%重叠时各帧窗函数之和恒为1
+        let window = (0..frame_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+            .collect();
+        Self {
+            inner,
+            speed,
+            clock: MediaClock::default(),
+            channels,
+            sample_rate,
+            frame_len,
+            hop,
+            tolerance: frames(TOLERANCE),
+            window,
+            base: Duration::ZERO,
+            consumed: 0,
+            stretching: false,
+            input: Vec::new(),
+            input_start: 0,
+            input_done: false,
+            analysis: 0.0,
+            prev: None,
+            overlap: vec![0.0; frame_len * channels],
+            ready: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// 此音源的媒体时钟
+    pub fn clock(&self) -> MediaClock {
+        self.clock.clone()
+    }
+
+    /// 帧号换算为媒体位置
+    fn media_pos(&self, frame: f64) -> Duration {
+        self.base + Duration::from_secs_f64(frame.max(0.0) / self.sample_rate as f64)
+    }
+
+    /// 从当前读取位置开始变速处理
+    fn start_stretch(&mut self) {
+        let frame = self.consumed / self.channels as u64;
+        self.stretching = true;
+        self.input.clear();
+        self.input_start = frame;
+        self.input_done = false;
+        self.analysis = frame as f64;
+        self.prev = None;
+        self.overlap.fill(0.0);
+        self.ready.clear();
+        self.finished = false;
+    }
+
+    /// 读取输入直到缓冲覆盖帧号`end`或输入结束
+    fn fill(&mut self, end: u64) {
+        let needed = (end.saturating_sub(self.input_start) as usize) * self.channels;
+        while !self.input_done && self.input.len() < needed {
+            match self.inner.next() {
+                Some(sample) => {
+                    self.input.push(sample);
+                    self.consumed += 1;
+                }
+                None => self.input_done = true,
+            }
+        }
+    }
+
+    /// 缓冲内可用的结束帧号
+    fn input_end(&self) -> u64 {
+        self.input_start + (self.input.len() / self.channels) as u64
+    }
+
+    /// 取某帧所有声道的平均值, 超出缓冲的部分视为静音
+    fn mono(&self, frame: u64) -> f32 {
+        let Some(offset) = frame.checked_sub(self.input_start) else {
+            return 0.0;
+        };
+        let idx = offset as usize * self.channels;
+        match self.input.get(idx..idx + self.channels) {
+            Some(samples) => samples.iter().sum::<f32>() / self.channels as f32,
+            None => 0.0,
+        }
+    }
+
+    /// 候选起点与参考片段的互相关
+    fn correlation(&self, reference: u64, candidate: u64, step: usize) -> f32 {
+        (0..self.hop)
+            .step_by(step)
+            .map(|i| self.mono(reference + i as u64) * self.mono(candidate + i as u64))
+            .sum()
+    }
+
+    /// 在`[lo, hi]`内寻找与参考片段最相似的起点, 先粗搜再细搜
+    fn best_match(&self, reference: u64, lo: u64, hi: u64) -> u64 {
+        let search = |candidates: &mut dyn Iterator<Item = u64>, step: usize| {
+            candidates
+                .map(|c| (c, self.correlation(reference, c, step)))
+                .fold((lo, f32::MIN), |best, cur| if cur.1 > best.1 { cur } else { best })
+                .0
+        };
+        let coarse = search(&mut (lo..=hi).step_by(COARSE_STEP), 2);
+        let fine_lo = coarse.saturating_sub(COARSE_STEP as u64).max(lo);
+        let fine_hi = (coarse + COARSE_STEP as u64).min(hi);
+        search(&mut (fine_lo..=fine_hi), 1)
+    }
+
+    /// 合成一个步长的输出
+    fn step(&mut self) {
+        let (ch, hop, tol) = (self.channels, self.hop, self.tolerance as u64);
+        let speed = self.speed.get().clamp(MIN_SPEED, MAX_SPEED) as f64;
+        let nominal = self.analysis.round() as u64;
+        let reference = self.prev.map(|prev| prev + hop as u64);
+        let mut end = nominal + tol + self.frame_len as u64;
+        if let Some(reference) = reference {
+            end = end.max(reference + self.frame_len as u64);
+        }
+        self.fill(end);
+        if self.input_done && nominal >= self.input_end() {
+            // 输入耗尽, 输出叠加缓冲中剩余的半帧后结束
+            self.ready.extend(self.overlap.drain(..hop * ch));
+            self.finished = true;
+            return;
+        }
+        let chosen = match reference {
+            Some(reference) => {
+                let lo = nominal.saturating_sub(tol).max(self.input_start);
+                self.best_match(reference, lo, nominal + tol)
+            }
+            None => nominal,
+        };
+        // 加窗后重叠相加
+        let offset = (chosen - self.input_start) as usize * ch;
+        for (i, w) in self.window.iter().enumerate() {
+            for c in 0..ch {
+                let sample = self.input.get(offset + i * ch + c).copied().unwrap_or(0.0);
+                self.overlap[i * ch + c] += sample * w;
+            }
+        }
+        // 前半帧已叠加完整, 可以输出
+        self.ready.extend(self.overlap.drain(..hop * ch));
+        self.overlap.resize(self.frame_len * ch, 0.0);
+        self.clock.set(self.media_pos(nominal as f64));
+        self.prev = Some(chosen);
+        self.analysis += hop as f64 * speed;
+        // 丢弃之后不再用到的输入
+        let keep_from = ((self.analysis as u64).saturating_sub(tol)).min(chosen + hop as u64);
+        if keep_from > self.input_start {
+            let drop = ((keep_from - self.input_start) as usize * ch).min(self.input.len());
+            self.input.drain(..drop);
+            self.input_start += (drop / ch) as u64;
+        }
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if !self.stretching {
+            // 仅在帧边界切换处理方式, 保证声道对齐
+            if self.consumed.is_multiple_of(self.channels as u64) {
+                let frame = self.consumed / self.channels as u64;
+                self.clock.set(self.media_pos(frame as f64));
+                if (self.speed.get() - 1.0).abs() > f32::EPSILON {
+                    self.start_stretch();
+                    return self.next();
+                }
+            }
+            let sample = self.inner.next()?;
+            self.consumed += 1;
+            return Some(sample);
+        }
+        loop {
+            if let Some(sample) = self.ready.pop_front() {
+                return Some(sample);
+            }
+            if self.finished {
+                return None;
+            }
+            self.step();
+        }
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        match self.stretching {
+            true => None,
+            false => self.inner.current_span_len(),
+        }
+    }
+    fn channels(&self) -> ChannelCount {
+        self.channels as ChannelCount
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        // 定位后重新起算, 速度为1.0时恢复直通
+        self.base = pos;
+        self.consumed = 0;
+        self.stretching = false;
+        self.ready.clear();
+        self.clock.set(pos);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// `secs`秒的立体声锯齿波, 1kHz采样率
+    fn saw(secs: usize) -> SamplesBuffer {
+        let samples: Vec<f32> = (0..secs * 1000 * 2)
+            .map(|i| (i / 2 % 100) as f32 / 100.0)
+            .collect();
+        SamplesBuffer::new(2, 1000, samples)
+    }
+
+    #[test]
+    fn normal_speed_passes_through() {
+        let input: Vec<f32> = saw(2).collect();
+        let stretch = TimeStretch::new(saw(2), SpeedControl::new(1.0));
+        let clock = stretch.clock();
+        assert_eq!(stretch.collect::<Vec<_>>(), input);
+        assert_eq!(clock.get(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn double_speed_halves_the_length() {
+        let stretch = TimeStretch::new(saw(4), SpeedControl::new(2.0));
+        let clock = stretch.clock();
+        let len = stretch.count();
+        // 输入4秒(8000个样本), 允许一帧(40ms)的误差
+        assert!((len as i64 - 4000).abs() <= 80, "{len}");
+        assert!(
+            clock.get() >= Duration::from_millis(3900),
+            "{:?}",
+            clock.get()
+        );
+    }
+
+    #[test]
+    fn clock_restarts_from_seek_position() {
+        let speed = SpeedControl::new(2.0);
+        let mut stretch = TimeStretch::new(saw(10), speed.clone());
+        let clock = stretch.clock();
+        stretch.by_ref().take(2000).for_each(drop);
+        stretch.try_seek(Duration::from_secs(6)).unwrap();
+        assert_eq!(clock.get(), Duration::from_secs(6));
+        // 变速时1秒的输出约对应2秒的原音频
+        stretch.by_ref().take(2000).for_each(drop);
+        let pos = clock.get();
+        assert!(
+            pos > Duration::from_millis(7800) && pos <= Duration::from_secs(8),
+            "{pos:?}"
+        );
+        // 恢复原速后从定位处直通
+        speed.set(1.0);
+        stretch.try_seek(Duration::from_secs(3)).unwrap();
+        stretch.by_ref().take(1000).for_each(drop);
+        assert_eq!(stretch.next(), saw(10).nth(7000));
+        assert_eq!(clock.get(), Duration::from_millis(3500));
+    }
+}
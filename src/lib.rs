@@ -1,9 +1,13 @@
 mod cli_config;
+pub mod dsp;
 pub mod player;
+#[cfg(test)]
+mod test_util;
 pub mod utils;
 pub mod view;
 pub use anyhow::{Result as AnyResult, anyhow};
 pub use cli_config::Args;
+pub use dsp::*;
 pub use player::*;
 pub use utils::*;
 pub use view::*;
@@ -1,4 +1,8 @@
-use mini_music::{AnyResult, Args, player::App, view::clear_screen};
+use mini_music::{
+    AnyResult, Args,
+    player::{App, Source},
+    view::clear_screen,
+};
 use std::{
     fs,
     io::{self, ErrorKind},
@@ -9,12 +13,18 @@ fn main() -> AnyResult<()> {
     // 解析cmdline参数
     let mut args = Args::default();
     args.get_dir();
-    if let Some(dir) = args.dir {
+    if let Some(file) = &args.playlist {
+        //  验证播放列表文件是否存在
+        if !fs::metadata(file)?.is_file() {
+            return Err(io::Error::new(ErrorKind::NotFound, "播放列表文件未找到!").into());
+        }
+        App::run(Source::Playlist(file), &args)?;
+    } else if let Some(dir) = &args.dir {
         //  验证目录是否正确
-        if !fs::metadata(&dir)?.is_dir() {
+        if !fs::metadata(dir)?.is_dir() {
             return Err(io::Error::new(ErrorKind::NotFound, "目录未找到!").into());
         }
-        App::run(&dir)?;
+        App::run(Source::Dir(dir), &args)?;
     }
     clear_screen();
     Ok(())
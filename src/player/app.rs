@@ -3,27 +3,112 @@ use std::{
     path::Path,
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 
-use crate::{AnyResult, PlayCore, SharedCore, utils::*, view::*};
+use crate::{
+    AnyResult, Args, PlayCore, SharedCore, Shuffler, SplitMix64, open_output, utils::*, view::*,
+};
+
+/// 定期保存播放进度的间隔, 异常退出时最多丢失这么久的进度
+const SESSION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 曲目来源
+pub enum Source<'a> {
+    /// 音乐目录
+    Dir(&'a Path),
+    /// 播放列表文件
+    Playlist(&'a Path),
+}
 
 pub struct App;
 impl App {
     /// 运行播放器
-    pub fn run(dir: &Path) -> AnyResult<()> {
-        let mut core = PlayCore::new()?;
-        core.initial(dir)?;
+    pub fn run(source: Source, args: &Args) -> AnyResult<()> {
+        let output = open_output(args.output, &args.wav_file)?;
+        let mut core = PlayCore::new(output)?;
+        // 限制在合理范围内, 也避免非法数值
+        let crossfade = args.crossfade.filter(|secs| !secs.is_nan()).unwrap_or(0.0);
+        core.crossfade = Duration::from_secs_f32(crossfade.clamp(0.0, 30.0));
+        core.replay_gain = ReplayGain::new(
+            args.replaygain.unwrap_or_default(),
+            args.preamp.unwrap_or(0.0),
+            args.prevent_clipping.unwrap_or(true),
+        );
+        core.volume = args.volume.filter(|v| !v.is_nan()).unwrap_or(1.0).clamp(0.0, 1.0);
+        core.muted = args.muted.unwrap_or(false);
+        // 用户预设同名时覆盖内置预设
+        for preset in &args.eq_presets {
+            match core.eq_presets.iter_mut().find(|p| p.name == preset.name) {
+                Some(builtin) => *builtin = preset.clone(),
+                None => core.eq_presets.push(preset.clone()),
+            }
+        }
+        if let Some(name) = &args.eq
+            && !core.select_eq(name)
+        {
+            core.message = Some(format!("未找到均衡器预设: {name}"));
+        }
+        if let Some(format) = &args.format {
+            core.title_format = format.clone();
+        }
+        // 歌词面板前后各最多10行
+        if let Some(before) = args.lyrics_before {
+            core.lyrics_before = before.min(10);
+        }
+        if let Some(after) = args.lyrics_after {
+            core.lyrics_after = after.min(10);
+        }
+        core.set_sleep(args.sleep);
+        // 指定种子时随机顺序可复现
+        let rng = args.seed.map_or_else(SplitMix64::from_time, SplitMix64::new);
+        // 上次播放的是同一个目录或播放列表时从上次的进度继续, 命令行指定的模式优先
+        let session = match (args.no_resume, &source) {
+            (true, _) => None,
+            (false, Source::Dir(dir)) => Session::load().filter(|s| s.same_source(Some(dir), None)),
+            (false, Source::Playlist(file)) => {
+                Session::load().filter(|s| s.same_source(None, Some(file)))
+            }
+        };
+        let shuffle = args.shuffle.or(session.as_ref().map(|s| s.shuffle));
+        core.shuffle = Shuffler::new(shuffle.unwrap_or_default(), rng);
+        core.repeat = args
+            .repeat
+            .or(session.as_ref().map(|s| s.repeat))
+            .unwrap_or_default();
+        match source {
+            Source::Dir(dir) => core.initial(dir, args.rescan, args.sort.unwrap_or_default())?,
+            // 播放列表保持文件中的顺序
+            Source::Playlist(file) => {
+                let missing = core.initial_playlist(file)?;
+                if !missing.is_empty() {
+                    println!("播放列表中有{}项不存在:", missing.len());
+                    for item in &missing {
+                        println!("  {item}");
+                    }
+                    core.message = Some(format!("播放列表中有{}项不存在, 已跳过", missing.len()));
+                }
+            }
+        }
+        let resumed = match &session {
+            Some(session) => core.resume(session)?,
+            None => false,
+        };
+        if !resumed {
+            core.play_from_start()?;
+        }
+        let mut saved_volume = (core.volume, core.muted);
         let shared_core = Arc::new(Mutex::new(core));
-        // 进入终端`raw mode`
+        // 进入终端`raw mode`, 出错返回时也由`_terminal`恢复
         enable_raw_mode()?;
+        let _terminal = TerminalGuard;
         let mut stdout = io::stdout();
         // 隐藏光标以防止闪烁
         execute!(stdout, cursor::Hide)?;
@@ -31,29 +116,61 @@ impl App {
         execute!(stdout, cursor::SavePosition)?;
         let ui_handle = ui_thread(Arc::clone(&shared_core));
         let key_handle = monitor_key_thread(Arc::clone(&shared_core));
-        // 主线程执行循环播放
+        // 主线程执行循环播放, 并定期保存播放进度
+        let mut saved_at = Instant::now();
         while !shared_core.lock().unwrap().is_exit() {
-            {
-                let mut core = shared_core.lock().unwrap();
-                if core.is_empty() {
-                    switch(&mut core, true);
-                    core.playback()?;
+            let mut core = shared_core.lock().unwrap();
+            core.tick()?;
+            if saved_at.elapsed() >= SESSION_INTERVAL && !core.is_exit() {
+                core.save_session();
+                // 音量只记录在配置文件中, 有变化时同样定期保存
+                if (core.volume, core.muted) != saved_volume {
+                    save_volume(&core);
+                    saved_volume = (core.volume, core.muted);
                 }
+                saved_at = Instant::now();
             }
-            thread::sleep(Duration::from_millis(200));
+            drop(core);
+            thread::sleep(Duration::from_millis(50));
         }
         // 等待子线程结束
         ui_handle.join().unwrap()?;
         key_handle.join().unwrap()?;
-        // 退出终端`raw mode`
-        execute!(
+        // 保存音量到配置文件
+        {
+            let core = shared_core.lock().unwrap();
+            save_volume(&core);
+            if let Some(preset) = core.eq_preset() {
+                Args::save_settings("Equalizer", &[("preset", preset.name.clone())]);
+            }
+            // 保存播放次数
+            if let Err(e) = core.library.save() {
+                eprintln!("保存音乐库索引失败: {e}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 保存音量与静音状态到配置文件的`[Playback]`
+fn save_volume(core: &PlayCore) {
+    Args::save_settings("Playback", &[
+        ("volume", core.volume.to_string()),
+        ("muted", core.muted.to_string()),
+    ]);
+}
+
+/// 离开作用域时退出终端`raw mode`, 回到锚点并显示光标
+struct TerminalGuard;
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(
             io::stdout(),
             cursor::RestorePosition, // 回到锚点
             cursor::Show             // 最后显示光标
-        )?;
-        disable_raw_mode()?;
-
-        Ok(())
+        );
+        let _ = disable_raw_mode();
     }
 }
 
@@ -62,7 +179,9 @@ fn ui_thread(shared_core: SharedCore) -> thread::JoinHandle<AnyResult<()>> {
     thread::spawn(move || -> AnyResult<()> {
         while !shared_core.lock().unwrap().is_exit() {
             {
-                update_ui(&shared_core.lock().unwrap())?;
+                let mut core = shared_core.lock().unwrap();
+                core.sync_track();
+                update_ui(&core)?;
             }
             thread::sleep(Duration::from_millis(100));
         }
@@ -76,10 +195,33 @@ fn monitor_key_thread(shared_core: SharedCore) -> thread::JoinHandle<AnyResult<(
     thread::spawn(move || -> AnyResult<()> {
         while !shared_core.lock().unwrap().is_exit() {
             if event::poll(Duration::from_millis(100))?
-                && let Event::Key(key) = event::read()?
+                && let Some(key) = read_key(&shared_core)?
                 && key.kind == KeyEventKind::Press
             {
+                let (view_open, prompting) = {
+                    let core = shared_core.lock().unwrap();
+                    (core.view.is_some(), core.prompt.is_some())
+                };
+                let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+                // 输入书签名称时, 按键都用于编辑名称
                 let op = match key.code {
+                    KeyCode::Enter if prompting => Some(PromptConfirm),
+                    KeyCode::Esc if prompting => Some(PromptCancel),
+                    KeyCode::Backspace if prompting => Some(PromptBackspace),
+                    KeyCode::Char(c) if prompting => Some(PromptInput(c)),
+                    _ if prompting => None,
+                    // 打开待播队列视图时, 方向键等用于视图操作
+                    KeyCode::Up if view_open && shift => Some(QueueMoveUp),
+                    KeyCode::Down if view_open && shift => Some(QueueMoveDown),
+                    KeyCode::Up if view_open => Some(CursorUp),
+                    KeyCode::Down if view_open => Some(CursorDown),
+                    KeyCode::Tab if view_open => Some(SwitchPane),
+                    KeyCode::Enter if view_open => Some(PlaySelected),
+                    KeyCode::Char('n') if view_open => Some(PlayNext),
+                    KeyCode::Char('q') if view_open => Some(Enqueue),
+                    KeyCode::Delete | KeyCode::Backspace if view_open => Some(Dequeue),
+                    KeyCode::Esc if view_open => Some(ToggleQueueView),
+                    KeyCode::Char('v') => Some(ToggleQueueView),
                     KeyCode::Char(' ') => Some(TogglePaused),
                     KeyCode::Char('c') => Some(Clean),
                     KeyCode::Left => Some(Backward),
@@ -87,14 +229,52 @@ fn monitor_key_thread(shared_core: SharedCore) -> thread::JoinHandle<AnyResult<(
                     KeyCode::Up => Some(Prev),
                     KeyCode::Down => Some(Next),
                     KeyCode::Esc => Some(Exit),
+                    KeyCode::Char('+' | '=') => Some(VolumeUp),
+                    KeyCode::Char('-') => Some(VolumeDown),
+                    KeyCode::Char('m') => Some(Mute),
+                    KeyCode::Char(']') => Some(SpeedUp),
+                    KeyCode::Char('[') => Some(SpeedDown),
+                    KeyCode::Char('\\') => Some(SpeedReset),
+                    KeyCode::Char('e') => Some(CycleEq),
+                    KeyCode::Char('a') => Some(MarkA),
+                    KeyCode::Char('b') => Some(MarkB),
+                    KeyCode::Char('x') => Some(ClearLoop),
+                    KeyCode::Char('t') => Some(CycleSleep),
+                    KeyCode::Char('s') => Some(CycleShuffle),
+                    KeyCode::Char('r') => Some(CycleRepeat),
+                    KeyCode::Char('w') => Some(SavePlaylist),
+                    KeyCode::Char('k') => Some(AddBookmark),
+                    KeyCode::Char('K') => Some(RemoveBookmark),
+                    KeyCode::Char(',') => Some(PrevBookmark),
+                    KeyCode::Char('.') => Some(NextBookmark),
                     _ => None,
                 };
                 if let Some(op) = op {
                     let mut core = shared_core.lock().unwrap();
-                    key_action(&mut core, op)?;
+                    // 操作失败不应中断按键监听, 显示提示即可
+                    core.message = None;
+                    if let Err(e) = key_action(&mut core, op) {
+                        core.message = Some(e.to_string());
+                    }
                 }
             }
         }
         Ok(())
     })
 }
+
+/// 读取一个终端事件, 按键事件以外返回`None`
+///
+/// 终端大小改变后原有内容会错位, 清屏并重设锚点后由界面线程重新绘制. 持有锁以免与界面刷新交错
+fn read_key(shared_core: &SharedCore) -> AnyResult<Option<KeyEvent>> {
+    match event::read()? {
+        Event::Key(key) => Ok(Some(key)),
+        Event::Resize(..) => {
+            let _core = shared_core.lock().unwrap();
+            clear_screen();
+            execute!(io::stdout(), cursor::MoveTo(0, 0), cursor::SavePosition)?;
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
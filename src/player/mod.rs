@@ -1,4 +1,12 @@
 mod app;
+mod output;
 mod play_service;
+mod playlist;
+mod queue;
+mod shuffle;
 pub use app::*;
+pub use output::*;
 pub use play_service::*;
+pub use playlist::*;
+pub use queue::*;
+pub use shuffle::*;
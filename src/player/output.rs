@@ -0,0 +1,205 @@
+use crate::AnyResult;
+use clap::ValueEnum;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rodio::{
+    OutputStream, OutputStreamBuilder, Source,
+    mixer::{self, Mixer, MixerSource},
+};
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 无设备后端使用的声道数
+const CHANNELS: u16 = 2;
+/// 无设备后端使用的采样率
+const SAMPLE_RATE: u32 = 44_100;
+/// 无设备后端每次消耗混音数据的间隔
+const PUMP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 音频输出后端
+///
+/// `PlayCore`的`Sink`连接到后端提供的混音器, 后端负责把混音结果送往声卡、文件, 或直接丢弃
+pub trait OutputBackend: Send {
+    /// 后端的混音器
+    fn mixer(&self) -> &Mixer;
+}
+
+/// 输出后端类型
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputKind {
+    /// 默认音频设备
+    #[default]
+    Device,
+    /// 无声输出, 按墙上时钟推进播放
+    Null,
+    /// 写入WAV文件
+    Wav,
+}
+
+/// 按类型打开输出后端, `wav_path`仅对`OutputKind::Wav`有效
+pub fn open_output(kind: OutputKind, wav_path: &Path) -> AnyResult<Box<dyn OutputBackend>> {
+    Ok(match kind {
+        OutputKind::Device => Box::new(DeviceOutput::open()?),
+        OutputKind::Null => Box::new(NullOutput::new()),
+        OutputKind::Wav => Box::new(WavOutput::create(wav_path)?),
+    })
+}
+
+/// 默认音频设备输出
+pub struct DeviceOutput {
+    /// 音频输出流句柄
+    stream: OutputStream,
+}
+impl DeviceOutput {
+    /// 获取链接默认音频设备输出流
+    pub fn open() -> AnyResult<Self> {
+        let mut stream = OutputStreamBuilder::open_default_stream()?;
+        // 避免退出时向终端打印提示
+        stream.log_on_drop(false);
+        Ok(Self { stream })
+    }
+}
+impl OutputBackend for DeviceOutput {
+    fn mixer(&self) -> &Mixer {
+        self.stream.mixer()
+    }
+}
+
+/// 无声输出, 按墙上时钟消耗混音数据并丢弃
+pub struct NullOutput {
+    mixer: Mixer,
+    _pump: Pump,
+}
+impl Default for NullOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl NullOutput {
+    pub fn new() -> Self {
+        let (mixer, source) = mixer::mixer(CHANNELS, SAMPLE_RATE);
+        let _pump = Pump::spawn(source, |_| true);
+        Self { mixer, _pump }
+    }
+}
+impl OutputBackend for NullOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+}
+
+/// WAV文件输出, 按墙上时钟把混音数据写入文件
+pub struct WavOutput {
+    mixer: Mixer,
+    _pump: Pump,
+}
+impl WavOutput {
+    /// 新建(或覆盖)WAV文件, 样本格式为32位浮点
+    pub fn create(path: &Path) -> AnyResult<Self> {
+        let spec = WavSpec {
+            channels: CHANNELS,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(path, spec)?;
+        let (mixer, source) = mixer::mixer(CHANNELS, SAMPLE_RATE);
+        // 写入失败时停止输出, `writer`在线程结束时被丢弃并自动补全文件头
+        let _pump = Pump::spawn(source, move |samples| {
+            samples.iter().all(|s| writer.write_sample(*s).is_ok())
+        });
+        Ok(Self { mixer, _pump })
+    }
+}
+impl OutputBackend for WavOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+}
+
+/// 后台线程, 代替声卡以实时速率拉取混音数据
+struct Pump {
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl Pump {
+    /// `consume`返回`false`时停止拉取
+    fn spawn<F>(mut source: MixerSource, mut consume: F) -> Self
+    where
+        F: FnMut(&[f32]) -> bool + Send + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = Arc::clone(&running);
+        let handle = thread::spawn(move || {
+            let samples_per_sec = (source.sample_rate() * source.channels() as u32) as f64;
+            let start = Instant::now();
+            let mut produced = 0u64;
+            let mut buf = Vec::new();
+            while flag.load(Ordering::Relaxed) {
+                // 按已流逝时间计算应产生的样本数, 并对齐到整帧
+                let due = (start.elapsed().as_secs_f64() * samples_per_sec) as u64;
+                let due = due - due % source.channels() as u64;
+                buf.clear();
+                // 没有声音时混音器返回`None`, 以静音补齐
+                buf.extend((produced..due).map(|_| source.next().unwrap_or(0.0)));
+                produced = due.max(produced);
+                if !consume(&buf) {
+                    break;
+                }
+                thread::sleep(PUMP_INTERVAL);
+            }
+        });
+        Self {
+            running,
+            handle: Some(handle),
+        }
+    }
+}
+impl Drop for Pump {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operation, key_action, test_util::TempDir};
+
+    #[test]
+    fn next_and_prev_switch_tracks() {
+        let dir = TempDir::new("switch");
+        let files = dir.wav_files(3, 20);
+        let mut core = dir.core_with(&files);
+        assert_eq!(core.get_audio_path().unwrap(), files[0]);
+        key_action(&mut core, Operation::Next).unwrap();
+        assert_eq!(core.get_audio_path().unwrap(), files[1]);
+        assert_eq!(core.src_time, 20);
+        key_action(&mut core, Operation::Prev).unwrap();
+        key_action(&mut core, Operation::Prev).unwrap();
+        // 列表循环, 第一首的上一首是最后一首
+        assert_eq!(core.get_audio_path().unwrap(), files[2]);
+    }
+
+    #[test]
+    fn forward_and_backward_seek() {
+        let dir = TempDir::new("seek");
+        let mut core = dir.core_with(&dir.wav_files(1, 20));
+        key_action(&mut core, Operation::Forward).unwrap();
+        key_action(&mut core, Operation::Forward).unwrap();
+        let pos = core.get_pos();
+        assert!(pos >= Duration::from_secs(10) && pos < Duration::from_secs(11), "{pos:?}");
+        key_action(&mut core, Operation::Backward).unwrap();
+        let pos = core.get_pos();
+        assert!(pos >= Duration::from_secs(5) && pos < Duration::from_secs(6), "{pos:?}");
+    }
+}
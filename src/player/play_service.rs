@@ -1,121 +1,847 @@
-use crate::{AnyResult, anyhow, utils::*};
-use rodio::{Decoder, OutputStream, OutputStreamBuilder, Source};
+use crate::{
+    AnyResult, EqControl, EqPreset, Equalizer, Fader, Gain, GainControl, MediaClock,
+    OutputBackend, Pane, PlayQueue, Playlist, PlaylistEntry, QueueView, RepeatMode, Segment,
+    ShuffleMode, Shuffler, SortKey, SpeedControl, SplitMix64, TimeStretch, anyhow, utils::*,
+};
+use rodio::{
+    Decoder, Source,
+    source::{EmptyCallback, SeekError},
+};
 use std::{
     collections::HashMap,
     fs::File,
     io::BufReader,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
+/// 剩余时长低于此值时预加载下一首
+const PRELOAD_AHEAD: Duration = Duration::from_secs(5);
+/// 不是从播放列表文件加载时, 播放列表保存到此文件
+const PLAYLIST_FILE: &str = "mini-playlist.m3u8";
+/// 待播队列保存到此文件
+const QUEUE_FILE: &str = "mini-queue.m3u8";
+/// 歌词面板默认在当前行前后各显示的行数
+const DEFAULT_LYRICS_CONTEXT: usize = 2;
+/// 不短于此时长的曲目记住切走时的位置, 切回时从该位置继续
+const LONG_TRACK: Duration = Duration::from_secs(10 * 60);
+/// 距开头或结尾不到此时长时不记住位置
+const RESUME_MARGIN: Duration = Duration::from_secs(30);
+/// 跳到上一个书签时, 刚越过不到此时长的书签视为当前书签, 跳过它
+const BOOKMARK_GRACE: Duration = Duration::from_secs(3);
+
+/// 曲目音源处理链: 解码 → 截取CUE表曲目的区间 → 均衡器 → 响度增益 → 变速
+type TrackSource = TimeStretch<Gain<Equalizer<Segment<Decoder<BufReader<File>>>>>>;
+
+/// 解码并解析完毕, 等待接续播放的曲目
+struct Preloaded {
+    /// 播放列表项的编号
+    id: u64,
+    /// 预加载代次, 与`PlayCore::started`相等时表示已开始播放
+    generation: u64,
+    metadata: TrackMetadata,
+    src_time: u64,
+    lyrics: Option<Vec<LyricLine>>,
+    /// 书签标识
+    mark_key: Option<String>,
+    /// 音频文件
+    path: PathBuf,
+    /// 响度归一化增益
+    gain: GainControl,
+    /// 媒体时钟
+    clock: MediaClock,
+    /// 淡出开关, 追加到`Sink`后才有
+    fade_out: Option<Arc<AtomicBool>>,
+}
+
+/// A-B循环区间, 两点都标记后在区间内循环播放
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AbLoop {
+    pub a: Option<Duration>,
+    pub b: Option<Duration>,
+}
+impl AbLoop {
+    /// 两点都已标记时返回循环区间
+    pub fn range(&self) -> Option<(Duration, Duration)> {
+        self.a.zip(self.b)
+    }
+}
+
 /// CLI音乐播放器核心结构体
 pub struct PlayCore {
-    /// 音频输出设备的句柄，管理音频流的播放
+    /// 连接到输出后端的接收器，管理音频流的播放
     sink: rodio::Sink,
-    /// 音频输出流句柄
-    _stream_handle: OutputStream,
-    /// 音乐文件索引映射（索引 -> 文件元数据）
-    pub audio_list: Option<HashMap<u32, PathBuf>>,
-    /// 当前播放曲目索引
-    pub current_audio_idx: u32,
-    /// 当前播放文件名（缓存显示用）
-    pub file_name: String,
-    /// 总曲目数
-    pub audio_total: u32,
+    /// 另一个接收器, 交叉淡化时上一首在此淡出
+    spare: rodio::Sink,
+    /// 当前曲目的淡出开关
+    fade_out: Arc<AtomicBool>,
+    /// 交叉淡化时长, 为0时无缝衔接
+    pub crossfade: Duration,
+    /// 响度归一化设置
+    pub replay_gain: ReplayGain,
+    /// 音量, 范围`0.0..=1.0`
+    pub volume: f32,
+    /// 是否静音
+    pub muted: bool,
+    /// 当前曲目的响度归一化增益
+    gain: GainControl,
+    /// 播放速度, 所有曲目共用
+    pub speed: SpeedControl,
+    /// 均衡器设置, 所有曲目共用
+    pub eq: EqControl,
+    /// 可选的均衡器预设
+    pub eq_presets: Vec<EqPreset>,
+    /// 当前均衡器预设的索引
+    pub eq_index: usize,
+    /// 当前曲目的媒体时钟, 即不受播放速度影响的播放位置
+    clock: MediaClock,
+    /// 音频输出后端
+    _output: Box<dyn OutputBackend>,
+    /// 持久化的音乐库索引
+    pub library: Library,
+    /// 音乐目录的文件监视, 无法监视时为`None`
+    watcher: Option<DirWatcher>,
+    /// 播放列表, 记录当前曲目的位置
+    pub playlist: Playlist,
+    /// 音乐目录, 从播放列表文件加载时为`None`
+    pub dir: Option<PathBuf>,
+    /// 加载播放列表的文件, 保存时写回该文件
+    pub playlist_file: Option<PathBuf>,
+    /// 播放列表文件中原有的各项, 保存时沿用其中的标题, 并写回无法解析的项
+    file_items: Vec<PlaylistItem>,
+    /// 随机播放
+    pub shuffle: Shuffler,
+    /// 待播队列
+    pub queue: PlayQueue,
+    /// 循环模式
+    pub repeat: RepeatMode,
+    /// 不循环时列表已播完, 不再自动切歌
+    finished: bool,
+    /// 下一首预加载失败, 当前曲目播完前不再重试
+    preload_failed: bool,
+    /// 当前曲目的元数据
+    pub metadata: TrackMetadata,
+    /// 曲目信息的显示格式, 见`TrackMetadata::format`
+    pub title_format: String,
+    /// 歌词面板中当前行之前显示的行数
+    pub lyrics_before: usize,
+    /// 歌词面板中当前行之后显示的行数
+    pub lyrics_after: usize,
     /// 当前曲目总时长
     pub src_time: u64,
     /// 当前曲目总时长的格式化字符串
     pub total_time: String,
-    /// 解析后的歌词数据（时间戳 -> 歌词文本）
-    pub lyrics: Option<Vec<(Duration, String)>>,
+    /// 回退定位时跳过的时长, `Sink`报告的位置需加上此偏移
+    pos_offset: Duration,
+    /// 解析后的歌词数据, 按时间排列
+    pub lyrics: Option<Vec<LyricLine>>,
+    /// 定时停止
+    pub sleep: Option<SleepTimer>,
+    /// 当前曲目的A-B循环, 切歌时清除
+    pub ab_loop: AbLoop,
+    /// 书签与长曲目的上次位置
+    pub bookmarks: Bookmarks,
+    /// 当前曲目的书签标识, 无法读取文件时为`None`
+    mark_key: Option<String>,
+    /// 书签标识对应的音频文件, 切歌时列表已指向下一首, 据此记录切走的曲目
+    mark_path: PathBuf,
+    /// 正在输入名称的书签
+    pub prompt: Option<BookmarkPrompt>,
+    /// 已追加到`Sink`队列尾部的下一首
+    preloaded: Option<Preloaded>,
+    /// 预加载代次计数
+    generation: u64,
+    /// 最近一次开始播放的预加载代次, 由音频线程在曲目衔接处写入
+    started: Arc<AtomicU64>,
+    /// 操作失败等提示信息
+    pub message: Option<String>,
+    /// 打开的待播队列视图
+    pub view: Option<QueueView>,
     /// 退出标志
     should_exit: bool,
 }
 pub type SharedCore = Arc<Mutex<PlayCore>>;
 
 impl PlayCore {
-    /// 新建播放器PlayCore实例
-    pub fn new() -> AnyResult<Self> {
-        // 获取链接默认音频设备输出流和其句柄
-        let _stream_handle = OutputStreamBuilder::open_default_stream()?;
-        // 创建一个接收器Sink
-        let sink = rodio::Sink::connect_new(_stream_handle.mixer());
+    /// 新建播放器PlayCore实例, 声音经由`output`输出
+    pub fn new(output: Box<dyn OutputBackend>) -> AnyResult<Self> {
+        PlayCore::with_bookmarks(output, Bookmarks::open())
+    }
+
+    /// 同[`PlayCore::new`], 但使用给定的书签
+    pub fn with_bookmarks(output: Box<dyn OutputBackend>, bookmarks: Bookmarks) -> AnyResult<Self> {
+        // 创建一个接收器Sink, 连接到输出后端的混音器
+        let sink = rodio::Sink::connect_new(output.mixer());
+        let spare = rodio::Sink::connect_new(output.mixer());
         Ok(Self {
             sink,
-            _stream_handle,
+            spare,
+            fade_out: Arc::new(AtomicBool::new(false)),
+            crossfade: Duration::ZERO,
+            replay_gain: ReplayGain::default(),
+            volume: 1.0,
+            muted: false,
+            gain: GainControl::new(1.0),
+            speed: SpeedControl::default(),
+            eq: EqControl::default(),
+            eq_presets: EqPreset::builtin(),
+            eq_index: 0,
+            clock: MediaClock::default(),
+            _output: output,
             total_time: String::new(),
-            file_name: String::new(),
-            audio_list: None,
-            current_audio_idx: 1,
-            audio_total: 0,
+            metadata: TrackMetadata::default(),
+            title_format: DEFAULT_TITLE_FORMAT.to_string(),
+            lyrics_before: DEFAULT_LYRICS_CONTEXT,
+            lyrics_after: DEFAULT_LYRICS_CONTEXT,
+            library: Library::default(),
+            watcher: None,
+            playlist: Playlist::default(),
+            dir: None,
+            playlist_file: None,
+            file_items: Vec::new(),
+            shuffle: Shuffler::new(ShuffleMode::Off, SplitMix64::from_time()),
+            queue: PlayQueue::default(),
+            repeat: RepeatMode::default(),
+            finished: false,
+            preload_failed: false,
             src_time: 0,
             lyrics: None,
+            pos_offset: Duration::ZERO,
+            sleep: None,
+            ab_loop: AbLoop::default(),
+            bookmarks,
+            mark_key: None,
+            mark_path: PathBuf::new(),
+            prompt: None,
+            preloaded: None,
+            generation: 0,
+            started: Arc::new(AtomicU64::new(0)),
+            message: None,
+            view: None,
             should_exit: false,
         })
     }
 
-    /// 初始化播放器
-    pub fn initial(&mut self, dir: &Path) -> AnyResult<()> {
-        // 加载音频列表
-        self.audio_list = load_audio_list(dir);
-        // 计算总曲目数
-        self.audio_total = self.audio_list.as_ref().unwrap().len() as u32;
-        // 执行首次播放
-        self.playback()?;
+    /// 加载音乐目录, `rescan`为`true`时重新探测音乐库中的全部文件
+    pub fn initial(&mut self, dir: &Path, rescan: bool, sort: SortKey) -> AnyResult<()> {
+        // 读取音乐库索引并加载音频列表
+        self.library = Library::open();
+        self.playlist = Playlist::new(load_audio_list(dir, &mut self.library, rescan));
+        self.playlist.sort(sort, &self.library);
+        // 监视目录, 运行中增删的文件随时反映到列表. 事件路径与音乐库一样为绝对路径
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        match DirWatcher::new(&dir) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(e) => self.message = Some(format!("无法监视音乐目录: {e}")),
+        }
+        self.dir = Some(dir);
         Ok(())
     }
 
+    /// 加载播放列表文件, 返回文件中不存在的项
+    pub fn initial_playlist(&mut self, file: &Path) -> AnyResult<Vec<String>> {
+        let loaded = load_playlist(file)?;
+        // 列表中的曲目同样记入音乐库, 供排序、随机播放和显示使用
+        self.library = Library::open();
+        for item in loaded.tracks() {
+            self.library.update(&item.path);
+        }
+        if let Err(e) = self.library.save() {
+            eprintln!("保存音乐库索引失败: {e}");
+        }
+        // 列表记录的标题与时长补充到标签缺失的曲目, 不写入索引
+        for item in loaded.tracks() {
+            self.library.apply_hint(item);
+        }
+        let paths = loaded.tracks().map(|item| item.path.clone());
+        self.playlist = Playlist::new(split_cue_tracks(paths, &self.library));
+        self.playlist_file = Some(file.to_path_buf());
+        let missing = loaded.missing().map(str::to_string).collect();
+        self.file_items = loaded.items;
+        if self.playlist.is_empty() {
+            return Err(anyhow!("播放列表中没有可播放的曲目"));
+        }
+        Ok(missing)
+    }
+
+    /// 从上次的会话继续播放, 上次的曲目已不在列表中时返回`false`
+    pub fn resume(&mut self, session: &Session) -> AnyResult<bool> {
+        let Some(idx) = session
+            .track
+            .as_ref()
+            .and_then(|key| self.playlist.position_of_key(key))
+        else {
+            return Ok(false);
+        };
+        for key in &session.queue {
+            if let Some(entry) = self.playlist.position_of_key(key).and_then(|i| self.playlist.get(i)) {
+                self.queue.enqueue(entry.id);
+            }
+        }
+        self.playlist.select(idx);
+        self.playback()?;
+        // 无法定位时从曲目开头播放, 不影响启动
+        if !session.position.is_zero()
+            && let Err(e) = self.seek(session.position)
+        {
+            self.message = Some(format!("无法恢复播放位置, 从头播放: {e}"));
+        }
+        Ok(true)
+    }
+
+    /// 当前的播放状态, 列表已播完时不记录曲目
+    pub fn session(&self) -> Session {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let track = match self.is_finished() {
+            true => None,
+            false => self.playlist.current_entry().map(PlaylistEntry::key),
+        };
+        let queue = self
+            .queue
+            .iter()
+            .filter_map(|id| self.playlist.position_of_id(*id))
+            .filter_map(|idx| self.playlist.get(idx))
+            .map(PlaylistEntry::key)
+            .collect();
+        Session {
+            dir: self.dir.clone(),
+            playlist: self.playlist_file.as_deref().map(canonical),
+            track,
+            position: self.get_pos(),
+            queue,
+            shuffle: self.shuffle.mode,
+            repeat: self.repeat,
+        }
+    }
+
+    /// 保存当前的播放状态与长曲目的位置, 失败时显示提示
+    pub fn save_session(&mut self) {
+        self.remember_position();
+        if let Err(e) = self.session().save().and_then(|_| self.bookmarks.save()) {
+            self.message = Some(format!("保存播放进度失败: {e}"));
+        }
+    }
+
+    /// 从列表开头播放, 随机播放时从随机的一首开始. 待播队列不为空时先播放队列
+    pub fn play_from_start(&mut self) -> AnyResult<()> {
+        let first = match self.queue.peek(&self.playlist) {
+            Some(idx) => Some(idx),
+            None => self.shuffle.first(&self.playlist, &self.library),
+        };
+        self.playlist.select(first.unwrap_or(0));
+        self.playback()
+    }
+
     pub fn decoder(&self, audio: &Path) -> AnyResult<Decoder<BufReader<File>>> {
-        // 解码音频
-        let file = BufReader::new(File::open(audio)?);
-        let source = Decoder::new(file)?;
+        // 解码音频, 由`File`构建的解码器已知字节长度, 支持直接定位
+        let source = Decoder::try_from(File::open(audio)?)?;
         Ok(source)
     }
-    pub fn get_duration(&self, source: &Decoder<BufReader<File>>) -> u64 {
+    /// 解码音频并截取曲目的区间, 整个文件为一首时不截取
+    fn segment(
+        &self,
+        audio: &Path,
+        cue: Option<&CueTrack>,
+    ) -> AnyResult<Segment<Decoder<BufReader<File>>>> {
+        let decoder = self.decoder(audio)?;
+        Ok(match cue {
+            Some(cue) => Segment::new(decoder, cue.start, cue.end),
+            None => Segment::new(decoder, Duration::ZERO, None),
+        })
+    }
+    pub fn get_duration<S: Source>(&self, source: &S) -> u64 {
         let src_duration = source
             .total_duration()
             .unwrap_or_else(|| Duration::from_secs(0));
         src_duration.as_secs()
     }
     pub fn get_audio_path(&self) -> AnyResult<PathBuf> {
-        if let Some(audio_map) = &self.audio_list
-            && let Some(audio) = audio_map.get(&self.current_audio_idx)
-        {
-            Ok(audio.into())
-        } else {
-            Err(anyhow!("无效的音频索引"))
+        match self.playlist.current_entry() {
+            Some(entry) => Ok(entry.path.clone()),
+            None => Err(anyhow!("无效的音频索引")),
         }
     }
 
     /// 播放指定索引的音频
     pub fn playback(&mut self) -> AnyResult<()> {
-        self.hold_state_clear();
-        //
-        let audio = self.get_audio_path()?;
-        // 尝试加载并解析歌词
-        self.lyrics = load_and_parse_lrc(&audio);
+        // 跳过已被删除但尚未移出列表的曲目, 之后没有可播放的曲目时视为列表已播完
+        while let Ok(audio) = self.get_audio_path()
+            && !audio.exists()
+        {
+            self.playlist.remove(self.playlist.current());
+            if !switch(self, true) {
+                self.finish();
+                return Ok(());
+            }
+        }
+        self.remember_position();
+        let (source, track) = self.prepare(self.playlist.current())?;
+        // 正在播放时与上一首交叉淡化, 否则直接切换
+        let crossfade = !self.crossfade.is_zero() && !self.is_empty() && !self.is_paused();
+        if crossfade {
+            self.swap_sink();
+        } else {
+            self.hold_state_clear();
+        }
+        self.pos_offset = Duration::ZERO;
+        self.finished = false;
+        // 沿用当前音量
+        self.apply_volume();
+        // 加载音频源, 并开始播放
+        self.fade_out = self.append(source, crossfade);
+        self.apply(track);
+        self.restore_position()
+    }
+
+    /// 当前曲目在原接收器上淡出, 改用另一个接收器播放
+    fn swap_sink(&mut self) {
+        self.fade_out.store(true, Ordering::Relaxed);
+        self.preloaded = None;
+        std::mem::swap(&mut self.sink, &mut self.spare);
+        // 另一个接收器可能还在淡出更早的曲目
+        self.sink.clear();
+        self.sink.play();
+    }
+
+    /// 解码指定索引的音频, 并解析歌词、时长、响度增益等信息
+    fn prepare(&mut self, idx: usize) -> AnyResult<(TrackSource, Preloaded)> {
+        let (id, audio, cue) = match self.playlist.get(idx) {
+            Some(entry) => (entry.id, entry.path.clone(), entry.cue.clone()),
+            None => return Err(anyhow!("无效的音频索引")),
+        };
         // 解码音频
-        let source = self.decoder(&audio)?;
+        let decoder = self.segment(&audio, cue.as_ref())?;
         // 获取音频时长
-        let src_time = self.get_duration(&source);
-        let minutes = src_time / 60;
-        let seconds = src_time % 60;
+        let src_time = self.get_duration(&decoder);
+        let probed = probe_file(&audio).ok();
+        // 优先使用CUE表与音乐库中的元数据, 其中含有播放列表文件补充的信息
+        let mut metadata = match self.playlist.get(idx).and_then(|e| e.metadata(&self.library)) {
+            Some(metadata) => metadata.clone(),
+            None => TrackMetadata::new(&audio, probed.as_ref()),
+        };
+        let tags = probed.map(|probed| probed.tags).unwrap_or_default();
+        let gain = self.replay_gain.gain_for(&audio, &tags);
+        let lyrics = load_and_parse_lrc(&audio, &tags);
+        // 标签缺失时采用歌词中的信息, 整张专辑的歌词不适用于其中的曲目
+        if let (Some(lyrics), None) = (&lyrics, &cue) {
+            metadata.apply_lyrics(&audio, lyrics);
+        }
+        // 音频无法给出时长时采用元数据中的时长
+        let src_time = match src_time {
+            0 => metadata.duration.map_or(0, |duration| duration.as_secs()),
+            src_time => src_time,
+        };
+        // 整张专辑的歌词按曲目起点平移, 只保留区间内的部分
+        let lyrics = lyrics.map(|lyrics| match &cue {
+            Some(cue) => lyrics
+                .lines
+                .into_iter()
+                .filter(|line| line.time >= cue.start && cue.end.is_none_or(|end| line.time < end))
+                .map(|line| line.map_times(|time| time.saturating_sub(cue.start)))
+                .collect(),
+            None => lyrics.lines,
+        });
+        let source = Equalizer::new(decoder, self.eq.clone());
+        let source = TimeStretch::new(Gain::new(source, gain.clone()), self.speed.clone());
+        self.generation += 1;
+        let track = Preloaded {
+            id,
+            generation: self.generation,
+            metadata,
+            src_time,
+            lyrics: lyrics.filter(|lyrics| !lyrics.is_empty()),
+            mark_key: track_mark_key(&audio, cue.map(|cue| cue.start.as_millis() as u64)),
+            path: audio,
+            gain,
+            clock: source.clock(),
+            fade_out: None,
+        };
+        Ok((source, track))
+    }
+
+    /// 切换当前曲目信息
+    fn apply(&mut self, track: Preloaded) {
+        // 曲目已被移出列表时保持当前位置
+        if let Some(idx) = self.playlist.position_of_id(track.id) {
+            self.follow(track.id);
+            self.playlist.select(idx);
+            if let Some(entry) = self.playlist.get(idx) {
+                self.library.record_play(&entry.path);
+            }
+        }
+        self.metadata = track.metadata;
+        self.lyrics = track.lyrics;
+        self.mark_key = track.mark_key;
+        self.mark_path = track.path;
+        self.prompt = None;
+        self.src_time = track.src_time;
+        self.gain = track.gain;
+        self.clock = track.clock;
+        self.ab_loop = AbLoop::default();
+        self.preload_failed = false;
+        let minutes = track.src_time / 60;
+        let seconds = track.src_time % 60;
         self.total_time = format!("{:02}:{:02}", minutes, seconds);
-        self.src_time = src_time;
-        // 音量初始化
-        self.set_volume(1.0);
-        // 加载音频源, 并开始播放
-        self.append(source);
-        //获取不含扩展名的文件名
-        self.file_name = audio.file_stem().unwrap().to_string_lossy().to_string();
+    }
+
+    /// 推进待播队列或随机顺序到刚开始播放(或跳过)的曲目
+    fn follow(&mut self, id: u64) {
+        // 队列中的曲目不计入随机顺序, 队列播完后沿原顺序继续
+        if !self.queue.follow(id) {
+            self.shuffle.follow(id);
+        }
+    }
+
+    /// 由播放循环定期调用: 同步曲目信息, 预加载下一首, 队列播完时切到下一首
+    pub fn tick(&mut self) -> AnyResult<()> {
+        self.sync_track();
+        self.apply_fs_changes();
+        if self.sleep.is_some() {
+            // 定时停止: 最后30秒逐渐淡出, 到时后按退出流程停止
+            if self.sleep_remaining().is_some_and(|r| r.is_zero()) {
+                return key_action(self, Operation::Exit);
+            }
+            self.apply_volume();
+        }
+        let last_track = self.sleep.is_some_and(|sleep| sleep.is_last_track());
+        if self.is_empty() {
+            if last_track {
+                return key_action(self, Operation::Exit);
+            }
+            if !self.finished {
+                self.advance();
+            }
+            return Ok(());
+        }
+        if self.src_time == 0 || self.is_paused() {
+            return Ok(());
+        }
+        // A-B循环期间不自动切歌, 越过B点时回到A点
+        if let Some((a, b)) = self.ab_loop.range() {
+            if self.get_pos() >= b
+                && let Err(e) = self.seek(a)
+            {
+                self.message = Some(e.to_string());
+                self.ab_loop = AbLoop::default();
+            }
+            return Ok(());
+        }
+        // 定时停止前的最后一首, 或不循环时的最后一首不再接续下一首
+        if last_track || self.finished {
+            return Ok(());
+        }
+        let remaining = self.track_remaining();
+        if self.crossfade.is_zero() {
+            // 下一首无法解码时等当前曲目播完, 由`advance`跳过
+            if self.preloaded.is_none()
+                && remaining <= PRELOAD_AHEAD
+                && !self.preload_failed
+                && let Err(e) = self.preload()
+            {
+                self.preload_failed = true;
+                self.message = Some(format!("下一首无法播放: {e}"));
+            }
+        } else if remaining <= self.crossfade && self.get_pos() >= self.crossfade {
+            // 淡入完成后才开始下一次淡化, 避免短曲目反复触发
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// 当前曲目播放完毕, 自动切到下一首, 没有下一首时停止
+    ///
+    /// 无法解码的曲目显示提示后跳过, 每首最多尝试一次, 全部失败时视为列表已播完
+    fn advance(&mut self) {
+        let mut counted = false;
+        for _ in 0..self.playlist.len() {
+            let Some(idx) = self.next_available() else {
+                break;
+            };
+            if !counted && let Some(sleep) = &mut self.sleep {
+                sleep.track_finished();
+            }
+            counted = true;
+            self.playlist.select(idx);
+            match self.playback() {
+                Ok(()) => return,
+                Err(e) => {
+                    if let Some(id) = self.playlist.get(idx).map(|entry| entry.id) {
+                        self.follow(id);
+                    }
+                    self.message = Some(format!("已跳过无法播放的曲目: {e}"));
+                }
+            }
+        }
+        self.finished = true;
+    }
+
+    /// 自动接续的下一首, 单曲循环且队列为空时为当前曲目, 不循环且已到末尾时为`None`
+    fn following(&mut self) -> Option<usize> {
+        match self.repeat {
+            RepeatMode::One if self.queue.peek(&self.playlist).is_none() => {
+                Some(self.playlist.current())
+            }
+            _ => next_index(self, true),
+        }
+    }
+
+    /// 停止播放, 视为列表已播完
+    pub fn finish(&mut self) {
+        self.hold_state_clear();
+        self.finished = true;
+    }
+
+    /// 列表已播完且没有曲目在播放
+    pub fn is_finished(&self) -> bool {
+        self.finished && self.is_empty()
+    }
+
+    /// 当前曲目的剩余时长, 已按播放速度换算为实际时长
+    fn track_remaining(&self) -> Duration {
+        Duration::from_secs(self.src_time)
+            .saturating_sub(self.get_pos())
+            .div_f32(self.speed.get())
+    }
+
+    /// 距定时停止的剩余时长, 按曲目数定时且不止一首时为`None`
+    pub fn sleep_remaining(&self) -> Option<Duration> {
+        let sleep = self.sleep?;
+        match sleep.remaining() {
+            Some(remaining) => Some(remaining),
+            None if sleep.is_last_track() && self.src_time > 0 => Some(self.track_remaining()),
+            None => None,
+        }
+    }
+
+    /// 设置定时停止, `None`为取消
+    pub fn set_sleep(&mut self, mode: Option<SleepMode>) {
+        self.sleep = mode.map(SleepTimer::new);
+        self.apply_volume();
+    }
+
+    /// 解码下一首并追加到`Sink`队列, 当前曲目结束后无缝接续
+    fn preload(&mut self) -> AnyResult<()> {
+        // 没有下一首时当前曲目播完即停止
+        let Some(idx) = self.next_available() else {
+            self.finished = true;
+            return Ok(());
+        };
+        let (source, mut track) = self.prepare(idx)?;
+        // 下一首的第一个样本之前触发回调, 标记曲目衔接点
+        let started = Arc::clone(&self.started);
+        let generation = track.generation;
+        self.sink.append(EmptyCallback::new(Box::new(move || {
+            started.store(generation, Ordering::SeqCst);
+        })));
+        track.fade_out = Some(self.append(source, false));
+        self.preloaded = Some(track);
+        Ok(())
+    }
+
+    /// 自动接续的下一首的索引, 已被删除的曲目先移出列表
+    fn next_available(&mut self) -> Option<usize> {
+        loop {
+            let idx = self.following()?;
+            match self.playlist.get(idx) {
+                Some(entry) if !entry.path.exists() && self.playlist.len() > 1 => {
+                    self.playlist.remove(idx);
+                }
+                _ => return Some(idx),
+            }
+        }
+    }
+
+    /// 把音乐目录中的文件增删同步到列表和音乐库
+    fn apply_fs_changes(&mut self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        for change in watcher.changes() {
+            match change {
+                FsChange::Added(path) => {
+                    self.library.update(&path);
+                    self.add_track(path);
+                }
+                FsChange::Removed(path) => {
+                    self.library.remove(&path);
+                    loop {
+                        let found = self.playlist.iter().position(|e| e.path.starts_with(&path));
+                        match found {
+                            Some(idx) => {
+                                self.playlist.remove(idx);
+                            }
+                            None => break,
+                        }
+                    }
+                    self.queue.prune(&self.playlist);
+                    if let Some(view) = &mut self.view {
+                        view.clamp(self.playlist.len(), self.queue.len());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 追加曲目到列表末尾, 有CUE表时按曲目拆分, 已在列表中时忽略
+    pub fn add_track(&mut self, audio: PathBuf) {
+        if self.playlist.position_of(&audio).is_none() {
+            for (path, cue) in split_cue_tracks([audio], &self.library) {
+                self.playlist.push(path, cue);
+            }
+        }
+    }
+
+    /// 预加载的曲目已开始播放时, 切换当前曲目信息
+    pub fn sync_track(&mut self) {
+        let started = self.started.load(Ordering::SeqCst);
+        if self
+            .preloaded
+            .as_ref()
+            .is_some_and(|track| track.generation == started)
+        {
+            let mut track = self.preloaded.take().unwrap();
+            if let Some(sleep) = &mut self.sleep {
+                sleep.track_finished();
+            }
+            self.remember_position();
+            self.pos_offset = Duration::ZERO;
+            if let Some(fade_out) = track.fade_out.take() {
+                self.fade_out = fade_out;
+            }
+            self.apply(track);
+            if let Err(e) = self.restore_position() {
+                self.message = Some(e.to_string());
+            }
+        }
+    }
+
+    /// 记住长曲目当前的位置, 在开头或快结束时清除
+    fn remember_position(&mut self) {
+        let Some(key) = &self.mark_key else {
+            return;
+        };
+        let total = Duration::from_secs(self.src_time);
+        if total < LONG_TRACK {
+            return;
+        }
+        let pos = self.get_pos();
+        let resume = (pos >= RESUME_MARGIN && pos + RESUME_MARGIN < total).then_some(pos);
+        self.bookmarks.set_resume(key, &self.mark_path, resume);
+    }
+
+    /// 长曲目从上次切走时的位置继续
+    fn restore_position(&mut self) -> AnyResult<()> {
+        if Duration::from_secs(self.src_time) < LONG_TRACK {
+            return Ok(());
+        }
+        let resume = self.mark_key.as_ref().and_then(|key| self.bookmarks.get(key)?.resume);
+        if let Some(pos) = resume {
+            self.seek(pos)?;
+            self.message = Some(format!("从上次的位置{}继续", format_time(pos)));
+        }
         Ok(())
     }
 
-    /// 定位到当前音频的指定位置
+    /// 当前曲目的书签
+    pub fn current_bookmarks(&self) -> &[Bookmark] {
+        match &self.mark_key {
+            Some(key) => self.bookmarks.bookmarks(key),
+            None => &[],
+        }
+    }
+
+    /// 在当前位置开始添加书签, 等待输入名称
+    pub fn start_bookmark(&mut self) {
+        if self.mark_key.is_none() {
+            self.message = Some("当前曲目无法添加书签".to_string());
+            return;
+        }
+        self.prompt = Some(BookmarkPrompt {
+            pos: self.get_pos(),
+            name: String::new(),
+        });
+    }
+
+    /// 未输入名称时使用的书签名
+    pub fn default_bookmark_name(&self) -> String {
+        format!("书签{}", self.current_bookmarks().len() + 1)
+    }
+
+    /// 按输入的名称保存书签
+    pub fn add_bookmark(&mut self) -> AnyResult<()> {
+        let (Some(prompt), Some(key)) = (self.prompt.take(), &self.mark_key) else {
+            return Ok(());
+        };
+        let name = match prompt.name.trim() {
+            "" => self.default_bookmark_name(),
+            name => name.to_string(),
+        };
+        self.message = Some(format!("已添加书签 {} {}", name, format_time(prompt.pos)));
+        let bookmark = Bookmark {
+            name,
+            pos: prompt.pos,
+        };
+        self.bookmarks.add(key, &self.mark_path, bookmark);
+        self.bookmarks.save()
+    }
+
+    /// 跳到下一个/上一个书签
+    pub fn jump_bookmark(&mut self, next: bool) -> AnyResult<()> {
+        let pos = self.get_pos();
+        let bookmarks = self.current_bookmarks();
+        let target = match next {
+            true => bookmarks.iter().find(|b| b.pos > pos + Duration::from_millis(500)),
+            false => bookmarks
+                .iter()
+                .rfind(|b| b.pos + BOOKMARK_GRACE < pos),
+        };
+        let Some(bookmark) = target.cloned() else {
+            return Err(anyhow!("没有{}书签", if next { "下一个" } else { "上一个" }));
+        };
+        self.seek(bookmark.pos)?;
+        self.message = Some(format!("🔖 {} {}", bookmark.name, format_time(bookmark.pos)));
+        Ok(())
+    }
+
+    /// 删除当前位置之前最近的书签
+    pub fn remove_bookmark(&mut self) -> AnyResult<()> {
+        let pos = self.get_pos() + Duration::from_secs(1);
+        let idx = self.current_bookmarks().iter().rposition(|b| b.pos <= pos);
+        let (Some(idx), Some(key)) = (idx, &self.mark_key) else {
+            return Err(anyhow!("当前位置之前没有书签"));
+        };
+        if let Some(bookmark) = self.bookmarks.remove(key, idx) {
+            self.message = Some(format!("已删除书签 {}", bookmark.name));
+        }
+        self.bookmarks.save()
+    }
+
+    /// 在正在播放的解码器上定位到指定位置
+    ///
+    /// 解码器不支持定位时, 回退为重新解码并跳过目标位置之前的样本
     pub fn seek(&mut self, target_pos: Duration) -> AnyResult<()> {
-        self.playback()?;
-        let _ = self.sink.try_seek(target_pos);
+        match self.sink.try_seek(target_pos.saturating_sub(self.pos_offset)) {
+            Ok(()) => Ok(()),
+            Err(SeekError::NotSupported { .. }) => self.seek_by_skip(target_pos),
+            Err(e) => Err(anyhow!("定位失败: {e}")),
+        }
+    }
+
+    /// 重新解码当前音频, 跳过`target_pos`之前的内容
+    fn seek_by_skip(&mut self, target_pos: Duration) -> AnyResult<()> {
+        let audio = self.get_audio_path()?;
+        let cue = self.playlist.current_entry().and_then(|e| e.cue.as_ref());
+        let source = Equalizer::new(self.segment(&audio, cue)?, self.eq.clone());
+        let source = Gain::new(source, self.gain.clone());
+        let source = TimeStretch::new(source.skip_duration(target_pos), self.speed.clone());
+        self.hold_state_clear();
+        self.clock = source.clock();
+        self.fade_out = self.append(source, false);
+        self.pos_offset = target_pos;
         Ok(())
     }
     pub fn is_paused(&self) -> bool {
@@ -126,26 +852,228 @@ impl PlayCore {
     }
     pub fn play(&self) {
         self.sink.play();
+        self.spare.play();
     }
     pub fn pause(&self) {
         self.sink.pause();
+        self.spare.pause();
     }
     pub fn stop(&self) {
         self.sink.stop();
+        self.spare.stop();
     }
+    /// 当前曲目的播放位置, 不受播放速度影响
     pub fn get_pos(&self) -> Duration {
-        self.sink.get_pos()
+        self.clock.get() + self.pos_offset
+    }
+    /// 在当前位置标记A点, 已有的B点不在A点之后时一并清除
+    pub fn mark_a(&mut self) {
+        let pos = self.get_pos();
+        self.ab_loop.a = Some(pos);
+        if self.ab_loop.b.is_some_and(|b| b <= pos) {
+            self.ab_loop.b = None;
+        }
+    }
+    /// 在当前位置标记B点, 须在A点之后
+    pub fn mark_b(&mut self) -> AnyResult<()> {
+        let pos = self.get_pos();
+        match self.ab_loop.a {
+            Some(a) if pos > a => {
+                self.ab_loop.b = Some(pos);
+                Ok(())
+            }
+            Some(_) => Err(anyhow!("B点须在A点之后")),
+            None => Err(anyhow!("请先标记A点")),
+        }
+    }
+    /// 按名称选择均衡器预设, 找不到时返回`false`
+    pub fn select_eq(&mut self, name: &str) -> bool {
+        match self.eq_presets.iter().position(|p| p.name == name) {
+            Some(idx) => {
+                self.eq_index = idx;
+                self.eq.set(self.eq_presets[idx].gains);
+                true
+            }
+            None => false,
+        }
+    }
+    /// 切换到下一个均衡器预设
+    pub fn cycle_eq(&mut self) {
+        if self.eq_presets.is_empty() {
+            return;
+        }
+        self.eq_index = (self.eq_index + 1) % self.eq_presets.len();
+        self.eq.set(self.eq_presets[self.eq_index].gains);
+    }
+    /// 当前均衡器预设
+    pub fn eq_preset(&self) -> Option<&EqPreset> {
+        self.eq_presets.get(self.eq_index)
+    }
+    /// 切换随机播放模式
+    pub fn cycle_shuffle(&mut self) {
+        let mode = self.shuffle.mode.next();
+        self.shuffle.set_mode(mode, &self.playlist, &self.library);
+        self.resume_auto_advance();
+    }
+    /// 切换循环模式
+    pub fn cycle_repeat(&mut self) {
+        self.repeat = self.repeat.next();
+        self.resume_auto_advance();
+    }
+    /// 把列表中`idx`处的曲目加入待播队列, `next`为`true`时插入队首
+    pub fn enqueue(&mut self, idx: usize, next: bool) {
+        let Some(id) = self.playlist.get(idx).map(|e| e.id) else {
+            return;
+        };
+        match next {
+            true => self.queue.play_next(id),
+            false => self.queue.enqueue(id),
+        }
+        self.resume_auto_advance();
+    }
+    /// 保存播放列表, 队列视图的焦点在队列一栏时保存待播队列为M3U8. 返回保存的文件
+    pub fn save_list(&self) -> AnyResult<PathBuf> {
+        let save_queue = self.view.as_ref().is_some_and(|view| view.pane == Pane::Queue);
+        let (path, mut entries): (PathBuf, Vec<&PlaylistEntry>) = match save_queue {
+            true => (
+                PathBuf::from(QUEUE_FILE),
+                self.queue
+                    .iter()
+                    .filter_map(|id| self.playlist.position_of_id(*id))
+                    .filter_map(|idx| self.playlist.get(idx))
+                    .collect(),
+            ),
+            false => (
+                self.playlist_file
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(PLAYLIST_FILE)),
+                self.playlist.iter().collect(),
+            ),
+        };
+        // 播放列表文件无法记录区间, 同一文件中相邻的CUE表曲目只记录一次文件
+        entries.dedup_by(|b, a| b.cue.is_some() && a.path == b.path);
+        let items = match save_queue {
+            true => entries
+                .into_iter()
+                .map(|entry| self.list_item(entry, &HashMap::new()))
+                .collect(),
+            false => self.file_list_items(&entries),
+        };
+        save_playlist(&path, &items)?;
+        Ok(path)
+    }
+    /// 保存到播放列表文件的各项
+    ///
+    /// 文件中原有的曲目沿用文件记录的标题与时长. 无法解析的项原样写在文件中前一项之后,
+    /// 前一项已移出列表时写在末尾
+    fn file_list_items(&self, entries: &[&PlaylistEntry]) -> Vec<PlaylistItem> {
+        let recorded: HashMap<&Path, &PlaylistItem> = self
+            .file_items
+            .iter()
+            .filter(|item| item.location.is_none())
+            .map(|item| (item.path.as_path(), item))
+            .collect();
+        let mut unresolved: Vec<(Option<&Path>, &PlaylistItem)> = Vec::new();
+        let mut previous = None;
+        for item in &self.file_items {
+            match item.location {
+                Some(_) => unresolved.push((previous, item)),
+                None => previous = Some(item.path.as_path()),
+            }
+        }
+        let mut items = Vec::new();
+        let mut take_after = |items: &mut Vec<PlaylistItem>, anchor: Option<&Path>| {
+            unresolved.retain(|(previous, item)| {
+                let after = *previous == anchor;
+                if after {
+                    items.push((*item).clone());
+                }
+                !after
+            });
+        };
+        take_after(&mut items, None);
+        for entry in entries {
+            items.push(self.list_item(entry, &recorded));
+            take_after(&mut items, Some(&entry.path));
+        }
+        items.extend(unresolved.into_iter().map(|(_, item)| item.clone()));
+        items
+    }
+    /// 列表中曲目保存时的一项, 不在`recorded`中时按音乐库中的元数据记录
+    fn list_item(
+        &self,
+        entry: &PlaylistEntry,
+        recorded: &HashMap<&Path, &PlaylistItem>,
+    ) -> PlaylistItem {
+        if let Some(item) = recorded.get(entry.path.as_path()) {
+            return (*item).clone();
+        }
+        let meta = self.library.get(&entry.path).map(|e| &e.metadata);
+        PlaylistItem {
+            path: entry.path.clone(),
+            title: meta.map(|m| m.title.clone()),
+            artist: meta.and_then(|m| m.artist.clone()),
+            duration: meta.and_then(|m| m.duration),
+            ..Default::default()
+        }
+    }
+    /// 曲目的显示标题, 按显示格式渲染音乐库中的元数据, 不在音乐库中时为文件名
+    pub fn entry_title(&self, entry: &PlaylistEntry) -> String {
+        match entry.metadata(&self.library) {
+            Some(metadata) => metadata.format(&self.title_format),
+            None => entry
+                .path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+    /// 模式或队列变化后最后一首可能有了下一首, 仍在播放时恢复自动切歌
+    fn resume_auto_advance(&mut self) {
+        if !self.is_empty() {
+            self.finished = false;
+        }
+    }
+    /// 调节播放速度
+    pub fn change_speed(&mut self, delta: f32) {
+        // 按0.1取整, 避免浮点误差累积
+        self.speed.set(((self.speed.get() + delta) * 10.0).round() / 10.0);
     }
     pub fn set_volume(&self, value: f32) {
         self.sink.set_volume(value);
+        self.spare.set_volume(value);
+    }
+    /// 调节音量, 同时取消静音
+    pub fn change_volume(&mut self, delta: f32) {
+        // 按百分比取整, 避免浮点误差累积
+        self.volume = ((self.volume + delta) * 100.0).round().clamp(0.0, 100.0) / 100.0;
+        self.muted = false;
+        self.apply_volume();
+    }
+    /// 切换静音
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.apply_volume();
+    }
+    /// 按音量、静音状态与定时停止的淡出设置`Sink`音量
+    pub fn apply_volume(&self) {
+        let fade = self.sleep_remaining().map_or(1.0, sleep_fade);
+        self.set_volume(if self.muted { 0.0 } else { self.volume * fade });
     }
 
-    pub fn append(&self, source: Decoder<BufReader<File>>) {
+    /// 追加音源到`Sink`队列, 返回其淡出开关
+    pub fn append<S>(&self, source: S, fade_in: bool) -> Arc<AtomicBool>
+    where
+        S: Source + Send + 'static,
+    {
+        let (source, fade_out) = Fader::new(source, self.crossfade, fade_in);
         self.sink.append(source);
+        fade_out
     }
 
-    ///  确保清空Sink后不改变播放状态
+    ///  确保清空Sink后不改变播放状态, 预加载的曲目一并丢弃
     pub fn hold_state_clear(&mut self) {
+        self.preloaded = None;
         if !self.is_paused() {
             self.clear();
             self.play();
@@ -164,3 +1092,41 @@ impl PlayCore {
         self.should_exit = true;
     }
 }
+
+/// 格式化为`分:秒`
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RepeatMode, test_util::TempDir};
+
+    #[test]
+    fn deleted_last_track_finishes() {
+        let dir = TempDir::new("deleted-last");
+        let files = dir.wav_files(2, 20);
+        let mut core = dir.core_with(&files);
+        core.repeat = RepeatMode::Off;
+        std::fs::remove_file(&files[1]).unwrap();
+        core.playlist.select(1);
+        core.playback().unwrap();
+        // 不循环时没有下一首, 不会重播上一首
+        assert!(core.is_finished());
+        assert_eq!(core.playlist.len(), 1);
+    }
+
+    #[test]
+    fn deleted_first_track_plays_the_next() {
+        let dir = TempDir::new("deleted-first");
+        let files = dir.wav_files(3, 20);
+        let mut core = dir.core_with(&files);
+        core.repeat = RepeatMode::Off;
+        std::fs::remove_file(&files[0]).unwrap();
+        core.play_from_start().unwrap();
+        assert!(!core.is_finished());
+        assert_eq!(core.get_audio_path().unwrap(), files[1]);
+        assert_eq!(core.playlist.len(), 2);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{CueTrack, Library, TrackKey, TrackMetadata};
+use clap::ValueEnum;
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::Duration,
+};
+
+/// 播放列表的排序方式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// 按完整路径
+    #[default]
+    Path,
+    /// 按文件名自然顺序, 如`2`排在`10`之前
+    Name,
+    /// 按标题
+    Title,
+    /// 按艺术家、专辑、碟号、曲号
+    Artist,
+    /// 按加入音乐库的时间
+    Added,
+    /// 按时长
+    Duration,
+}
+
+/// 循环模式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// 播完最后一首后停止
+    Off,
+    /// 列表循环
+    #[default]
+    All,
+    /// 单曲循环, 手动切歌时仍切到下一首
+    One,
+}
+
+impl RepeatMode {
+    /// 按键依次切换的下一个模式
+    pub fn next(self) -> Self {
+        match self {
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+            RepeatMode::Off => RepeatMode::All,
+        }
+    }
+}
+
+/// 播放列表中的一项
+#[derive(Clone, Debug)]
+pub struct PlaylistEntry {
+    /// 列表内唯一且不变的编号, 插入、移除、移动或排序后仍可据此找到该项
+    pub id: u64,
+    pub path: PathBuf,
+    /// 由CUE表拆分出的曲目在文件中的区间与元数据, 整个文件为一首时为`None`
+    pub cue: Option<CueTrack>,
+}
+
+impl PlaylistEntry {
+    /// 曲目的元数据, CUE表拆分出的曲目取自CUE表, 其余取自音乐库
+    pub fn metadata<'a>(&'a self, library: &'a Library) -> Option<&'a TrackMetadata> {
+        match &self.cue {
+            Some(cue) => Some(&cue.metadata),
+            None => library.get(&self.path).map(|e| &e.metadata),
+        }
+    }
+    /// 记录到会话中的标识, 重新加载列表后据此找回该项
+    pub fn key(&self) -> TrackKey {
+        TrackKey {
+            path: self.path.clone(),
+            cue_start: self.cue.as_ref().map(|cue| cue.start.as_millis() as u64),
+        }
+    }
+}
+
+/// 有序的播放列表, 记录当前曲目的位置
+///
+/// 插入、移除、移动与排序都会同步调整当前位置, 使其仍指向同一曲目
+#[derive(Clone, Debug, Default)]
+pub struct Playlist {
+    entries: Vec<PlaylistEntry>,
+    /// 当前曲目的位置(从0开始)
+    current: usize,
+    /// 移除了位于开头的当前曲目, 此时当前位置在第一项之前, 下一首是第一项
+    before_first: bool,
+    /// 下一个新项的编号
+    next_id: u64,
+}
+
+impl Playlist {
+    pub fn new(tracks: impl IntoIterator<Item = (PathBuf, Option<CueTrack>)>) -> Self {
+        let mut playlist = Self::default();
+        for (path, cue) in tracks {
+            playlist.push(path, cue);
+        }
+        playlist
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &PlaylistEntry> {
+        self.entries.iter()
+    }
+    pub fn get(&self, idx: usize) -> Option<&PlaylistEntry> {
+        self.entries.get(idx)
+    }
+    /// 当前曲目的位置
+    pub fn current(&self) -> usize {
+        self.current
+    }
+    pub fn current_entry(&self) -> Option<&PlaylistEntry> {
+        self.entries.get(self.current)
+    }
+    /// 选择当前曲目, 超出范围时忽略
+    pub fn select(&mut self, idx: usize) {
+        if idx < self.entries.len() {
+            self.current = idx;
+            self.before_first = false;
+        }
+    }
+    /// 按编号查找位置
+    pub fn position_of_id(&self, id: u64) -> Option<usize> {
+        self.entries.iter().position(|e| e.id == id)
+    }
+    /// 按会话中记录的标识查找位置
+    pub fn position_of_key(&self, key: &TrackKey) -> Option<usize> {
+        self.entries.iter().position(|e| e.key() == *key)
+    }
+    /// 按路径查找第一个匹配的位置
+    pub fn position_of(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|e| e.path == path)
+    }
+    /// `idx`处的上一首/下一首的位置, 列表首尾循环
+    pub fn neighbor(&self, idx: usize, is_next: bool) -> usize {
+        match (is_next, self.entries.len()) {
+            (_, 0) => 0,
+            (true, _) if self.before_first && idx == self.current => 0,
+            (true, len) => (idx + 1) % len,
+            (false, len) => (idx + len - 1) % len,
+        }
+    }
+    /// `idx`处之后没有下一项
+    pub fn is_last(&self, idx: usize) -> bool {
+        !(self.before_first && idx == self.current) && idx + 1 >= self.entries.len()
+    }
+
+    /// 追加到末尾, 返回新项的编号
+    pub fn push(&mut self, path: PathBuf, cue: Option<CueTrack>) -> u64 {
+        self.insert(self.entries.len(), path, cue)
+    }
+    /// 插入到指定位置(超出范围时追加到末尾), 返回新项的编号
+    pub fn insert(&mut self, idx: usize, path: PathBuf, cue: Option<CueTrack>) -> u64 {
+        let idx = idx.min(self.entries.len());
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(idx, PlaylistEntry { id, path, cue });
+        if idx <= self.current && !self.before_first && self.entries.len() > 1 {
+            self.current += 1;
+        }
+        id
+    }
+    /// 移除指定位置的项
+    ///
+    /// 移除的正是当前曲目时, 当前位置指向上一首(位于开头时移到第一项之前), 这样下一首
+    /// 仍是原来的下一首
+    pub fn remove(&mut self, idx: usize) -> Option<PlaylistEntry> {
+        if idx >= self.entries.len() {
+            return None;
+        }
+        let entry = self.entries.remove(idx);
+        self.current = match self.current {
+            current if current > idx => current - 1,
+            current if current == idx && idx > 0 => idx - 1,
+            current if current == idx => {
+                self.before_first = !self.entries.is_empty();
+                0
+            }
+            current => current,
+        };
+        Some(entry)
+    }
+    /// 移动一项到新位置
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        if from >= self.entries.len() || to >= self.entries.len() || from == to {
+            return;
+        }
+        let current_id = self.entries[self.current].id;
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        if !self.before_first {
+            self.current = self.position_of_id(current_id).unwrap_or(0);
+        }
+    }
+    /// 排序, 相等的项保持原有顺序. 元数据取自音乐库
+    pub fn sort(&mut self, key: SortKey, library: &Library) {
+        let current_id = self.current_entry().map(|e| e.id);
+        // CUE表曲目的元数据存放在列表项中, 与列表项同生命周期
+        fn meta<'a>(e: &'a PlaylistEntry, library: &'a Library) -> &'a TrackMetadata {
+            static FALLBACK: LazyLock<TrackMetadata> = LazyLock::new(TrackMetadata::default);
+            e.metadata(library).unwrap_or(&FALLBACK)
+        }
+        let added = |e: &PlaylistEntry| library.get(&e.path).map_or(0, |e| e.added);
+        let file_name = |e: &PlaylistEntry| {
+            e.path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        match key {
+            // 同一文件中的曲目按起点排列
+            SortKey::Path => self.entries.sort_by(|a, b| {
+                let start = |e: &PlaylistEntry| e.cue.as_ref().map(|cue| cue.start);
+                a.path.cmp(&b.path).then_with(|| start(a).cmp(&start(b)))
+            }),
+            SortKey::Name => self
+                .entries
+                .sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b))),
+            SortKey::Title => self
+                .entries
+                .sort_by(|a, b| natural_cmp(&meta(a, library).title, &meta(b, library).title)),
+            SortKey::Artist => self.entries.sort_by(|a, b| {
+                let (a, b) = (meta(a, library), meta(b, library));
+                // 优先按专辑艺术家, 合辑中的曲目不会被拆散
+                let artist =
+                    |m: &TrackMetadata| m.album_artist.as_ref().or(m.artist.as_ref()).cloned();
+                let album = |m: &TrackMetadata| m.album.clone().unwrap_or_default();
+                natural_cmp(
+                    &artist(a).unwrap_or_default(),
+                    &artist(b).unwrap_or_default(),
+                )
+                .then_with(|| natural_cmp(&album(a), &album(b)))
+                .then_with(|| a.disc_number.cmp(&b.disc_number))
+                .then_with(|| a.track_number.cmp(&b.track_number))
+                .then_with(|| natural_cmp(&a.title, &b.title))
+            }),
+            SortKey::Added => self.entries.sort_by_key(added),
+            SortKey::Duration => self
+                .entries
+                .sort_by_key(|e| meta(e, library).duration.unwrap_or(Duration::ZERO)),
+        }
+        if let Some(idx) = current_id.and_then(|id| self.position_of_id(id))
+            && !self.before_first
+        {
+            self.current = idx;
+        }
+    }
+}
+
+/// 自然顺序比较: 连续数字按数值比较, 其余字符不区分大小写
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits
+                };
+                let (x, y) = (take(&mut a), take(&mut b));
+                let (xt, yt) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                // 先比位数再逐位比较, 不受数值大小限制
+                let ord = xt.len().cmp(&yt.len()).then_with(|| xt.cmp(yt));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(names: &[&str]) -> Playlist {
+        Playlist::new(names.iter().map(|name| (PathBuf::from(name), None)))
+    }
+
+    fn names(playlist: &Playlist) -> Vec<String> {
+        playlist
+            .iter()
+            .map(|e| e.path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn current(playlist: &Playlist) -> String {
+        playlist
+            .current_entry()
+            .unwrap()
+            .path
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn insert_before_or_at_current_keeps_current() {
+        let mut list = playlist(&["a", "b", "c"]);
+        list.select(1);
+        list.insert(0, PathBuf::from("x"), None);
+        assert_eq!((list.current(), current(&list)), (2, "b".to_string()));
+        list.insert(2, PathBuf::from("y"), None);
+        assert_eq!((list.current(), current(&list)), (3, "b".to_string()));
+        list.insert(4, PathBuf::from("z"), None);
+        assert_eq!(list.current(), 3);
+        assert_eq!(names(&list), ["x", "a", "y", "b", "z", "c"]);
+    }
+
+    #[test]
+    fn remove_before_at_and_after_current() {
+        let mut list = playlist(&["a", "b", "c", "d", "e"]);
+        list.select(2);
+        list.remove(3);
+        assert_eq!(current(&list), "c");
+        list.remove(0);
+        assert_eq!((list.current(), current(&list)), (1, "c".to_string()));
+        // 移除当前曲目后指向上一首, 下一首仍是原来的下一首
+        list.remove(1);
+        assert_eq!(current(&list), "b");
+        assert_eq!(list.neighbor(list.current(), true), 1);
+        assert_eq!(names(&list), ["b", "e"]);
+        assert!(list.remove(2).is_none());
+    }
+
+    #[test]
+    fn remove_current_first_entry_keeps_next() {
+        let mut list = playlist(&["a", "b", "c"]);
+        list.remove(0);
+        // 当前位置在第一项之前: 下一首是第一项, 上一首是最后一项
+        assert!(!list.is_last(list.current()));
+        assert_eq!(list.neighbor(list.current(), true), 0);
+        assert_eq!(list.neighbor(list.current(), false), 1);
+        // 插入到开头的项成为下一首
+        list.insert(0, PathBuf::from("x"), None);
+        assert_eq!(list.neighbor(list.current(), true), 0);
+        list.select(0);
+        assert_eq!(list.neighbor(list.current(), true), 1);
+        assert!(list.is_last(2));
+    }
+
+    #[test]
+    fn remove_only_entry_empties_list() {
+        let mut list = playlist(&["a"]);
+        list.remove(0);
+        assert!(list.is_empty());
+        assert!(list.is_last(list.current()));
+    }
+
+    #[test]
+    fn move_entry_across_current() {
+        let mut list = playlist(&["a", "b", "c", "d"]);
+        list.select(1);
+        list.move_entry(0, 3);
+        assert_eq!(names(&list), ["b", "c", "d", "a"]);
+        assert_eq!((list.current(), current(&list)), (0, "b".to_string()));
+        list.move_entry(3, 0);
+        assert_eq!((list.current(), current(&list)), (1, "b".to_string()));
+        list.move_entry(1, 2);
+        assert_eq!((list.current(), current(&list)), (2, "b".to_string()));
+    }
+
+    #[test]
+    fn sort_by_name_is_natural_and_keeps_current() {
+        let mut list = playlist(&["/m/Track 10.mp3", "/m/track 2.mp3", "/m/Track 1.mp3"]);
+        list.select(1);
+        list.sort(SortKey::Name, &Library::default());
+        assert_eq!(
+            names(&list),
+            ["/m/Track 1.mp3", "/m/track 2.mp3", "/m/Track 10.mp3"]
+        );
+        assert_eq!(current(&list), "/m/track 2.mp3");
+    }
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("2", "10"), Ordering::Less);
+        assert_eq!(natural_cmp("a10b", "a9b"), Ordering::Greater);
+        assert_eq!(natural_cmp("disc 02", "Disc 2"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "B"), Ordering::Less);
+        assert_eq!(natural_cmp("track", "track 1"), Ordering::Less);
+        // 超出整数范围的数字也按数值比较
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+}
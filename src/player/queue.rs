@@ -0,0 +1,177 @@
+use crate::Playlist;
+
+/// 待播队列, 优先于播放列表顺序播放
+///
+/// 记录播放列表项的编号, 列表增删、排序后仍然有效. 播放队列中的曲目时不移动列表中的
+/// 播放位置(`anchor`), 队列播完后从该位置继续
+#[derive(Clone, Debug, Default)]
+pub struct PlayQueue {
+    ids: Vec<u64>,
+    /// 开始播放队列前所在的列表项, 队列播完后从此处接续
+    anchor: Option<u64>,
+}
+
+impl PlayQueue {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &u64> {
+        self.ids.iter()
+    }
+    /// 插入到队首, 当前曲目之后播放
+    pub fn play_next(&mut self, id: u64) {
+        self.ids.insert(0, id);
+    }
+    /// 追加到队尾
+    pub fn enqueue(&mut self, id: u64) {
+        self.ids.push(id);
+    }
+    /// 移除指定位置的项
+    pub fn remove(&mut self, idx: usize) -> Option<u64> {
+        (idx < self.ids.len()).then(|| self.ids.remove(idx))
+    }
+    /// 移动一项到新位置
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        if from < self.ids.len() && to < self.ids.len() {
+            let id = self.ids.remove(from);
+            self.ids.insert(to, id);
+        }
+    }
+
+    /// 删除已被移出播放列表的曲目
+    pub fn prune(&mut self, playlist: &Playlist) {
+        self.ids.retain(|id| playlist.position_of_id(*id).is_some());
+    }
+
+    /// 队首曲目在播放列表中的位置, 已被移出列表的曲目从队列中删除
+    ///
+    /// 尚未开始播放队列时记下当前的列表位置
+    pub fn peek(&mut self, playlist: &Playlist) -> Option<usize> {
+        while let Some(&id) = self.ids.first() {
+            match playlist.position_of_id(id) {
+                Some(idx) => {
+                    if self.anchor.is_none() {
+                        self.anchor = playlist.current_entry().map(|e| e.id);
+                    }
+                    return Some(idx);
+                }
+                None => {
+                    self.ids.remove(0);
+                }
+            }
+        }
+        None
+    }
+
+    /// 曲目开始播放时调用, 返回是否来自队列
+    ///
+    /// 来自队列时从队首取出, 否则清除记下的列表位置
+    pub fn follow(&mut self, id: u64) -> bool {
+        if self.ids.first() == Some(&id) {
+            self.ids.remove(0);
+            true
+        } else {
+            self.anchor = None;
+            false
+        }
+    }
+
+    /// 播放队列期间, 列表中原来的播放位置
+    pub fn anchor(&self, playlist: &Playlist) -> Option<usize> {
+        playlist.position_of_id(self.anchor?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn playlist(count: usize) -> Playlist {
+        let mut playlist =
+            Playlist::new((0..count).map(|i| (PathBuf::from(format!("{i}.mp3")), None)));
+        playlist.select(1);
+        playlist
+    }
+
+    /// 像播放器一样播放队首曲目, 返回其位置
+    fn play_queued(queue: &mut PlayQueue, playlist: &mut Playlist) -> Option<usize> {
+        let idx = queue.peek(playlist)?;
+        playlist.select(idx);
+        assert!(queue.follow(playlist.current_entry()?.id));
+        Some(idx)
+    }
+
+    fn id(playlist: &Playlist, idx: usize) -> u64 {
+        playlist.get(idx).unwrap().id
+    }
+
+    #[test]
+    fn queue_plays_before_the_playlist() {
+        let mut list = playlist(5);
+        let mut queue = PlayQueue::default();
+        queue.enqueue(id(&list, 3));
+        queue.play_next(id(&list, 4));
+        queue.enqueue(id(&list, 0));
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), [4, 3, 0]);
+        assert_eq!(play_queued(&mut queue, &mut list), Some(4));
+        assert_eq!(play_queued(&mut queue, &mut list), Some(3));
+        assert_eq!(play_queued(&mut queue, &mut list), Some(0));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn resumes_from_anchor_after_queue_drains() {
+        let mut list = playlist(5);
+        let mut queue = PlayQueue::default();
+        assert_eq!(queue.anchor(&list), None);
+        queue.enqueue(id(&list, 4));
+        queue.enqueue(id(&list, 3));
+        play_queued(&mut queue, &mut list);
+        play_queued(&mut queue, &mut list);
+        assert_eq!(queue.peek(&list), None);
+        // 列表位置仍是开始播放队列前的第二首, 之后接着播放第三首
+        assert_eq!(queue.anchor(&list), Some(1));
+        // 列表插入后仍指向同一项
+        list.insert(0, PathBuf::from("new.mp3"), None);
+        assert_eq!(queue.anchor(&list), Some(2));
+        // 按列表播放后不再需要
+        list.select(3);
+        assert!(!queue.follow(id(&list, 3)));
+        assert_eq!(queue.anchor(&list), None);
+    }
+
+    #[test]
+    fn removed_entries_are_skipped() {
+        let mut list = playlist(4);
+        let mut queue = PlayQueue::default();
+        for idx in [2, 3, 0] {
+            queue.enqueue(id(&list, idx));
+        }
+        list.remove(2);
+        // 队首已被移出列表, 跳到下一项
+        assert_eq!(queue.peek(&list), Some(2));
+        assert_eq!(queue.len(), 2);
+        list.remove(2);
+        queue.prune(&list);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn move_and_remove_by_position() {
+        let mut queue = PlayQueue::default();
+        for id in [10, 11, 12] {
+            queue.enqueue(id);
+        }
+        queue.move_entry(2, 0);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), [12, 10, 11]);
+        queue.move_entry(0, 3);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), [12, 10, 11]);
+        assert_eq!(queue.remove(1), Some(10));
+        assert_eq!(queue.remove(2), None);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), [12, 11]);
+    }
+}
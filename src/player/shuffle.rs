@@ -0,0 +1,365 @@
+use crate::{Library, Playlist};
+use clap::ValueEnum;
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// 随机播放模式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShuffleMode {
+    /// 按列表顺序
+    #[default]
+    Off,
+    /// 打乱全部曲目, 每首播完一轮后才会重复
+    Track,
+    /// 打乱专辑顺序, 专辑内按列表顺序
+    Album,
+    /// 加权随机, 偏向评分高、播放次数少的曲目
+    Weighted,
+}
+
+impl ShuffleMode {
+    /// 按键依次切换的下一个模式
+    pub fn next(self) -> Self {
+        match self {
+            ShuffleMode::Off => ShuffleMode::Track,
+            ShuffleMode::Track => ShuffleMode::Album,
+            ShuffleMode::Album => ShuffleMode::Weighted,
+            ShuffleMode::Weighted => ShuffleMode::Off,
+        }
+    }
+}
+
+/// SplitMix64伪随机数生成器, 相同种子得到相同序列
+#[derive(Clone, Debug)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+    /// 以当前时间为种子
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self(nanos as u64)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// `0..n`内的随机数, `n`须大于0
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+    /// `0.0..1.0`内的随机数
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// Fisher–Yates洗牌
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// 随机播放的顺序
+///
+/// `order`同时是已播放的历史与预先排好的后续曲目, 记录播放列表项的编号, 因此列表增删、
+/// 排序后仍然有效. 上一首沿历史回退, 下一首沿顺序前进, 顺序用完时再生成一轮
+#[derive(Clone, Debug)]
+pub struct Shuffler {
+    pub mode: ShuffleMode,
+    rng: SplitMix64,
+    order: Vec<u64>,
+    /// 当前曲目在`order`中的位置
+    pos: usize,
+}
+
+impl Shuffler {
+    pub fn new(mode: ShuffleMode, rng: SplitMix64) -> Self {
+        Self {
+            mode,
+            rng,
+            order: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// 切换模式, 从当前曲目重新开始排列
+    pub fn set_mode(&mut self, mode: ShuffleMode, playlist: &Playlist, library: &Library) {
+        self.mode = mode;
+        self.order = playlist.current_entry().map(|e| e.id).into_iter().collect();
+        self.pos = 0;
+        // 立即排好本轮顺序, 不循环时据此判断何时播完
+        if !self.order.is_empty() {
+            self.extend(playlist, library);
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.mode != ShuffleMode::Off
+    }
+
+    /// 上一首/下一首在播放列表中的位置, 不移动当前位置
+    ///
+    /// 需要时生成后续顺序, 因此预加载与随后的切歌得到同一首. 没有历史可回退时返回`None`
+    pub fn peek(&mut self, is_next: bool, playlist: &Playlist, library: &Library) -> Option<usize> {
+        self.sync(playlist);
+        loop {
+            let target = match is_next {
+                true => self.pos + 1,
+                false => self.pos.checked_sub(1)?,
+            };
+            if target >= self.order.len() {
+                self.extend(playlist, library);
+            }
+            // 列表中只剩当前曲目等情况下生成不出后续曲目
+            if target >= self.order.len() {
+                return None;
+            }
+            // 已被移出列表的曲目从顺序中删除
+            match playlist.position_of_id(self.order[target]) {
+                Some(idx) => return Some(idx),
+                None => {
+                    self.order.remove(target);
+                    if target < self.pos {
+                        self.pos -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 本轮随机顺序是否已播完. 加权随机没有轮次, 总有下一首
+    pub fn at_end(&self, playlist: &Playlist) -> bool {
+        if self.mode == ShuffleMode::Weighted {
+            return false;
+        }
+        // 之后的曲目可能已被移出列表
+        let rest = self.order.get(self.pos + 1..).unwrap_or_default();
+        !rest.iter().any(|id| playlist.position_of_id(*id).is_some())
+    }
+
+    /// 曲目开始播放时调用, 使顺序中的当前位置跟随实际播放的曲目
+    pub fn follow(&mut self, id: u64) {
+        if self.order.get(self.pos) == Some(&id) {
+            return;
+        }
+        if self.order.get(self.pos + 1) == Some(&id) {
+            self.pos += 1;
+        } else if self.pos > 0 && self.order.get(self.pos - 1) == Some(&id) {
+            self.pos -= 1;
+        } else {
+            // 手动跳转的曲目插入到当前位置之后
+            let at = (self.pos + 1).min(self.order.len());
+            self.order.insert(at, id);
+            self.pos = at;
+        }
+    }
+
+    /// 开始随机播放时选择第一首, 未开启随机播放时返回`None`
+    pub fn first(&mut self, playlist: &Playlist, library: &Library) -> Option<usize> {
+        self.order.clear();
+        self.pos = 0;
+        if !self.is_on() {
+            return None;
+        }
+        self.extend(playlist, library);
+        playlist.position_of_id(*self.order.first()?)
+    }
+
+    /// 当前曲目不在顺序中时(如刚开启随机播放)以它为起点
+    fn sync(&mut self, playlist: &Playlist) {
+        if self.order.is_empty()
+            && let Some(entry) = playlist.current_entry()
+        {
+            self.order.push(entry.id);
+            self.pos = 0;
+        }
+    }
+
+    /// 按当前模式生成后续顺序
+    fn extend(&mut self, playlist: &Playlist, library: &Library) {
+        if playlist.is_empty() {
+            return;
+        }
+        let last = self.order.last().copied();
+        match self.mode {
+            ShuffleMode::Off => {}
+            ShuffleMode::Track => {
+                // 刚开启时当前曲目已在播放, 不计入第一轮
+                let skip = last.filter(|_| self.order.len() == 1);
+                let mut ids: Vec<u64> = playlist
+                    .iter()
+                    .map(|e| e.id)
+                    .filter(|id| Some(*id) != skip)
+                    .collect();
+                self.rng.shuffle(&mut ids);
+                // 避免新一轮的第一首与上一首相同
+                if ids.len() > 1 && ids.first() == last.as_ref() {
+                    let swap = 1 + self.rng.below(ids.len() - 1);
+                    ids.swap(0, swap);
+                }
+                self.order.extend(ids);
+            }
+            ShuffleMode::Album => {
+                let mut albums = albums(playlist, library);
+                self.rng.shuffle(&mut albums);
+                // 刚开启时先播完当前专辑
+                if self.order.len() == 1
+                    && let Some(current) = albums.iter().position(|a| a.contains(&self.order[0]))
+                {
+                    let album = albums.remove(current);
+                    let at = album.iter().position(|id| Some(*id) == last).unwrap_or(0);
+                    self.order.extend(&album[at + 1..]);
+                }
+                self.order.extend(albums.into_iter().flatten());
+            }
+            ShuffleMode::Weighted => {
+                let entries: Vec<_> = playlist.iter().filter(|e| Some(e.id) != last).collect();
+                let Some(first) = entries.first() else {
+                    self.order.extend(last);
+                    return;
+                };
+                let weights: Vec<f64> = entries.iter().map(|e| weight(library, &e.path)).collect();
+                let mut pick = self.rng.next_f64() * weights.iter().sum::<f64>();
+                let chosen = entries
+                    .iter()
+                    .zip(&weights)
+                    .find(|(_, w)| {
+                        pick -= **w;
+                        pick < 0.0
+                    })
+                    .map_or(first.id, |(e, _)| e.id);
+                self.order.push(chosen);
+            }
+        }
+    }
+}
+
+/// 按专辑分组, 组内保持列表顺序. 没有专辑标签时按所在目录分组
+fn albums(playlist: &Playlist, library: &Library) -> Vec<Vec<u64>> {
+    let mut groups: Vec<Vec<u64>> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for entry in playlist.iter() {
+        let meta = entry.metadata(library);
+        let key = match meta.and_then(|m| m.album.as_ref().map(|album| (m, album))) {
+            Some((m, album)) => {
+                let artist = m.album_artist.as_ref().or(m.artist.as_ref());
+                format!("album:{}\0{album}", artist.map_or("", |a| a.as_str()))
+            }
+            None => format!("dir:{}", entry.path.parent().unwrap_or(Path::new("")).display()),
+        };
+        let idx = *index.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[idx].push(entry.id);
+    }
+    groups
+}
+
+/// 加权随机的权重: 评分越高、播放次数越少越容易被选中. 未评分按中等评分计
+fn weight(library: &Library, path: &Path) -> f64 {
+    let entry = library.get(path);
+    let rating = entry.and_then(|e| e.metadata.rating).unwrap_or(0.5) as f64;
+    let plays = entry.map_or(0, |e| e.play_count) as f64;
+    (0.25 + rating) / (1.0 + plays).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// `dirs`个目录, 每个目录`per_dir`首曲目
+    fn playlist(dirs: usize, per_dir: usize) -> Playlist {
+        Playlist::new((0..dirs * per_dir).map(|i| {
+            let path = PathBuf::from(format!("/music/{}/{}.mp3", i / per_dir, i % per_dir));
+            (path, None)
+        }))
+    }
+
+    /// 像播放器一样切到上一首/下一首, 返回新的位置
+    fn step(shuffler: &mut Shuffler, playlist: &mut Playlist, is_next: bool) -> Option<usize> {
+        let idx = shuffler.peek(is_next, playlist, &Library::default())?;
+        playlist.select(idx);
+        shuffler.follow(playlist.current_entry()?.id);
+        Some(idx)
+    }
+
+    /// 从第一首开始依次播放`count`首
+    fn play(mode: ShuffleMode, seed: u64, playlist: &mut Playlist, count: usize) -> Vec<usize> {
+        let library = Library::default();
+        let mut shuffler = Shuffler::new(mode, SplitMix64::new(seed));
+        let first = shuffler.first(playlist, &library).unwrap();
+        playlist.select(first);
+        let mut order = vec![first];
+        for _ in 1..count {
+            order.push(step(&mut shuffler, playlist, true).unwrap());
+        }
+        order
+    }
+
+    #[test]
+    fn splitmix_is_deterministic() {
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        let mut a = SplitMix64::new(42);
+        let mut b = SplitMix64::new(42);
+        assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+    }
+
+    #[test]
+    fn track_shuffle_follows_seed() {
+        let order = play(ShuffleMode::Track, 7, &mut playlist(1, 8), 8);
+        assert_eq!(order, play(ShuffleMode::Track, 7, &mut playlist(1, 8), 8));
+        assert_ne!(order, play(ShuffleMode::Track, 8, &mut playlist(1, 8), 8));
+        // 一轮内每首恰好播放一次
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn prev_walks_back_through_history() {
+        let mut list = playlist(1, 8);
+        let library = Library::default();
+        let mut shuffler = Shuffler::new(ShuffleMode::Track, SplitMix64::new(3));
+        let first = shuffler.first(&list, &library).unwrap();
+        list.select(first);
+        let mut history = vec![first];
+        for _ in 0..4 {
+            history.push(step(&mut shuffler, &mut list, true).unwrap());
+        }
+        for expected in history.iter().rev().skip(1) {
+            assert_eq!(step(&mut shuffler, &mut list, false), Some(*expected));
+        }
+        // 历史的开头没有上一首
+        assert_eq!(shuffler.peek(false, &list, &library), None);
+        // 回退后再前进, 沿原来的顺序播放
+        assert_eq!(step(&mut shuffler, &mut list, true), Some(history[1]));
+    }
+
+    #[test]
+    fn album_shuffle_keeps_albums_contiguous() {
+        let per_dir = 3;
+        let order = play(ShuffleMode::Album, 11, &mut playlist(4, per_dir), 12);
+        for album in order.chunks(per_dir) {
+            // 专辑内按列表顺序
+            let first = album[0];
+            assert_eq!(first % per_dir, 0, "{order:?}");
+            assert_eq!(album, [first, first + 1, first + 2], "{order:?}");
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{Bookmarks, NullOutput, PlayCore, Playlist};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::{fs, path::PathBuf};
+
+/// 测试用的临时目录, 离开作用域时删除
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("mini-music-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// 创建空文件(可含子目录), 返回目录的绝对路径
+    pub fn files(&self, files: &[&str]) -> PathBuf {
+        for file in files {
+            let path = self.0.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        self.0.canonicalize().unwrap()
+    }
+
+    /// 生成`count`首`secs`秒的静音WAV
+    pub fn wav_files(&self, count: usize, secs: u32) -> Vec<PathBuf> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        (0..count)
+            .map(|i| {
+                let path = self.0.join(format!("{i}.wav"));
+                let mut writer = WavWriter::create(&path, spec).unwrap();
+                for _ in 0..spec.sample_rate * secs {
+                    writer.write_sample(0i16).unwrap();
+                }
+                writer.finalize().unwrap();
+                path
+            })
+            .collect()
+    }
+
+    /// 在无声输出上播放`files`, 书签保存在本目录中
+    pub fn core_with(&self, files: &[PathBuf]) -> PlayCore {
+        let output = Box::new(NullOutput::new());
+        let mut core = PlayCore::with_bookmarks(output, Bookmarks::open_in(&self.0)).unwrap();
+        core.playlist = Playlist::new(files.iter().map(|path| (path.clone(), None)));
+        core.play_from_start().unwrap();
+        core
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,24 +1,40 @@
-use std::{collections::HashMap, path::{Path, PathBuf}};
-use walkdir::WalkDir;
+use crate::{CueTrack, Library};
+use std::path::{Path, PathBuf};
 
 const EXT_LIST: [&str; 7] = ["mp3", "m4a", "flac", "aac", "wav", "ogg", "ape"];
-/// 使用扩展名过滤文件, 使用`WalkDir`递归遍历目录, 加载音频列表
-pub fn load_audio_list(dir: &Path) -> Option<HashMap<u32, PathBuf>> {
-    //
-    let mut index = 1;
-    let mut audio_map = HashMap::new();
+/// 扫描目录并更新音乐库索引, 加载音频列表. `rescan`为`true`时重新探测全部文件
+pub fn load_audio_list(
+    dir: &Path,
+    library: &mut Library,
+    rescan: bool,
+) -> Vec<(PathBuf, Option<CueTrack>)> {
+    let audio_list = library.scan(dir, rescan);
+    // 索引保存失败只影响下次启动的速度
+    if let Err(e) = library.save() {
+        eprintln!("保存音乐库索引失败: {e}");
+    }
+    split_cue_tracks(audio_list, library)
+}
 
-    // 使用 WalkDir 递归遍历目录
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file()
-            && let Some(ext) = path.extension()
-            && EXT_LIST.contains(&ext.to_str().unwrap())
-        {
-            audio_map.insert(index, path.to_path_buf());
-            index += 1;
+/// 带CUE表的文件展开为其中的各曲目, 其余文件原样保留
+pub fn split_cue_tracks(
+    paths: impl IntoIterator<Item = PathBuf>,
+    library: &Library,
+) -> Vec<(PathBuf, Option<CueTrack>)> {
+    let mut tracks = Vec::new();
+    for path in paths {
+        let cue_tracks = library.cue_tracks(&path);
+        match cue_tracks.is_empty() {
+            true => tracks.push((path, None)),
+            false => tracks.extend(cue_tracks.into_iter().map(|cue| (path.clone(), Some(cue)))),
         }
     }
+    tracks
+}
 
-    Some(audio_map)
+/// 使用扩展名判断是否为支持的音频文件
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXT_LIST.contains(&ext.to_ascii_lowercase().as_str()))
 }
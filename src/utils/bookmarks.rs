@@ -0,0 +1,232 @@
+use crate::{AnyResult, anyhow};
+use directories::ProjectDirs;
+use ini::Ini;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// 书签文件名
+const BOOKMARKS_FILE: &str = "bookmarks.ini";
+/// 计算曲目标识时读取文件首尾各这么多字节
+const HASH_SPAN: u64 = 64 * 1024;
+
+/// 曲目中的一个书签
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub pos: Duration,
+}
+
+/// 一首曲目的书签与上次播放到的位置
+#[derive(Clone, Debug, Default)]
+pub struct TrackMarks {
+    /// 最近一次的文件路径, 只用于查看书签文件时辨认曲目
+    pub path: PathBuf,
+    /// 按位置排列的书签
+    pub bookmarks: Vec<Bookmark>,
+    /// 切走时的播放位置, 切回时从这里继续
+    pub resume: Option<Duration>,
+}
+
+/// 正在输入名称的书签, 位置取自按下按键的时刻
+#[derive(Clone, Debug)]
+pub struct BookmarkPrompt {
+    pub pos: Duration,
+    pub name: String,
+}
+
+/// 持久化的书签
+///
+/// 保存在数据目录的`bookmarks.ini`中, 以`track_mark_key`得到的标识为节名,
+/// 文件移动或改名后书签仍然有效
+#[derive(Default)]
+pub struct Bookmarks {
+    tracks: HashMap<String, TrackMarks>,
+    /// 书签文件, 找不到数据目录时为`None`
+    file: Option<PathBuf>,
+    /// 有尚未写入文件的修改
+    dirty: bool,
+}
+
+impl Bookmarks {
+    /// 书签文件路径, 与音乐库索引同在数据目录
+    fn file_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "mini-music").map(|dirs| dirs.data_dir().join(BOOKMARKS_FILE))
+    }
+
+    /// 读取数据目录中的书签文件, 不存在或无法读取时为空
+    pub fn open() -> Self {
+        Bookmarks::load(Bookmarks::file_path())
+    }
+
+    /// 读取`dir`目录中的书签文件, 之后也保存到这里
+    pub fn open_in(dir: &Path) -> Self {
+        Bookmarks::load(Some(dir.join(BOOKMARKS_FILE)))
+    }
+
+    fn load(file: Option<PathBuf>) -> Self {
+        let Some(conf) = file.as_ref().and_then(|path| Ini::load_from_file(path).ok()) else {
+            return Self {
+                file,
+                ..Self::default()
+            };
+        };
+        let mut tracks = HashMap::new();
+        for (key, section) in conf.iter() {
+            let Some(key) = key else {
+                continue;
+            };
+            let mut marks = TrackMarks::default();
+            // 书签以位置(毫秒)为键、名称为值
+            for (name, value) in section.iter() {
+                match name {
+                    "path" => marks.path = PathBuf::from(value),
+                    "resume" => marks.resume = value.parse().ok().map(Duration::from_millis),
+                    ms => {
+                        if let Ok(ms) = ms.parse() {
+                            marks.bookmarks.push(Bookmark {
+                                name: value.to_string(),
+                                pos: Duration::from_millis(ms),
+                            });
+                        }
+                    }
+                }
+            }
+            marks.bookmarks.sort_by_key(|b| b.pos);
+            tracks.insert(key.to_string(), marks);
+        }
+        Self {
+            tracks,
+            file,
+            dirty: false,
+        }
+    }
+
+    /// 有修改时写入书签文件
+    pub fn save(&mut self) -> AnyResult<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let path = self.file.as_ref().ok_or(anyhow!("找不到数据目录"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut conf = Ini::new();
+        let mut keys: Vec<&String> = self.tracks.keys().collect();
+        keys.sort();
+        for key in keys {
+            let marks = &self.tracks[key];
+            let mut section = conf.with_section(Some(key.as_str()));
+            section.set("path", marks.path.to_string_lossy());
+            if let Some(resume) = marks.resume {
+                section.set("resume", resume.as_millis().to_string());
+            }
+            for bookmark in &marks.bookmarks {
+                section.set(bookmark.pos.as_millis().to_string(), bookmark.name.as_str());
+            }
+        }
+        // 先写临时文件再替换, 避免中断时损坏书签
+        let tmp = path.with_extension("tmp");
+        conf.write_to_file(&tmp)?;
+        fs::rename(tmp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// 曲目的书签与上次的位置
+    pub fn get(&self, key: &str) -> Option<&TrackMarks> {
+        self.tracks.get(key)
+    }
+
+    /// 曲目的书签, 没有时为空
+    pub fn bookmarks(&self, key: &str) -> &[Bookmark] {
+        self.get(key)
+            .map_or(&[], |marks| marks.bookmarks.as_slice())
+    }
+
+    /// 添加书签, 同一位置已有书签时改为新名称
+    pub fn add(&mut self, key: &str, path: &Path, bookmark: Bookmark) {
+        let marks = self.entry(key, path);
+        let pos = bookmark.pos.as_millis();
+        marks.bookmarks.retain(|b| b.pos.as_millis() != pos);
+        let at = marks.bookmarks.partition_point(|b| b.pos < bookmark.pos);
+        marks.bookmarks.insert(at, bookmark);
+        self.dirty = true;
+    }
+
+    /// 移除书签, 返回被移除的书签
+    pub fn remove(&mut self, key: &str, idx: usize) -> Option<Bookmark> {
+        let marks = self.tracks.get_mut(key)?;
+        if idx >= marks.bookmarks.len() {
+            return None;
+        }
+        let bookmark = marks.bookmarks.remove(idx);
+        if marks.bookmarks.is_empty() && marks.resume.is_none() {
+            self.tracks.remove(key);
+        }
+        self.dirty = true;
+        Some(bookmark)
+    }
+
+    /// 记录切走时的播放位置, `None`为清除
+    pub fn set_resume(&mut self, key: &str, path: &Path, resume: Option<Duration>) {
+        if self.get(key).map(|marks| marks.resume) == Some(resume) {
+            return;
+        }
+        match resume {
+            Some(_) => self.entry(key, path).resume = resume,
+            None => {
+                let Some(marks) = self.tracks.get_mut(key) else {
+                    return;
+                };
+                marks.resume = None;
+                if marks.bookmarks.is_empty() {
+                    self.tracks.remove(key);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// 取得曲目的记录, 没有时新建, 并更新最近的路径
+    fn entry(&mut self, key: &str, path: &Path) -> &mut TrackMarks {
+        let marks = self.tracks.entry(key.to_string()).or_default();
+        marks.path = path.to_path_buf();
+        marks
+    }
+}
+
+/// 曲目的书签标识: 文件大小与首尾内容的FNV-1a散列, CUE表曲目附加起点(毫秒)
+///
+/// 只读取首尾各64KiB, 长音频也能很快算出
+pub fn track_mark_key(path: &Path, cue_start: Option<u64>) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    let mut buf = Vec::new();
+    file.by_ref().take(HASH_SPAN).read_to_end(&mut buf).ok()?;
+    feed(&buf);
+    if size > HASH_SPAN {
+        buf.clear();
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(HASH_SPAN).max(HASH_SPAN),
+        ))
+        .ok()?;
+        file.read_to_end(&mut buf).ok()?;
+        feed(&buf);
+    }
+    Some(match cue_start {
+        Some(start) => format!("{size:x}-{hash:016x}@{start}"),
+        None => format!("{size:x}-{hash:016x}"),
+    })
+}
@@ -1,5 +1,12 @@
 use std::time::Duration;
-use crate::{AnyResult, player::PlayCore, view::clear_screen};
+use crate::{
+    AnyResult, Pane, QueueView, RepeatMode, SleepMode, player::PlayCore, view::clear_screen,
+};
+
+/// 音量调节步长
+const VOLUME_STEP: f32 = 0.05;
+/// 播放速度调节步长
+const SPEED_STEP: f32 = 0.1;
 
 /// 键盘操作映射
 ///
@@ -19,27 +26,101 @@ pub enum Operation {
     Exit,
     /// 手动清屏
     Clean,
+    /// 增大音量
+    VolumeUp,
+    /// 减小音量
+    VolumeDown,
+    /// 切换静音
+    Mute,
+    /// 加快播放速度
+    SpeedUp,
+    /// 减慢播放速度
+    SpeedDown,
+    /// 恢复原速
+    SpeedReset,
+    /// 切换均衡器预设
+    CycleEq,
+    /// 标记A-B循环的A点
+    MarkA,
+    /// 标记A-B循环的B点
+    MarkB,
+    /// 清除A-B循环
+    ClearLoop,
+    /// 切换定时停止
+    CycleSleep,
+    /// 切换随机播放模式
+    CycleShuffle,
+    /// 切换循环模式
+    CycleRepeat,
+    /// 打开/关闭待播队列视图
+    ToggleQueueView,
+    /// 视图中光标上移
+    CursorUp,
+    /// 视图中光标下移
+    CursorDown,
+    /// 视图中切换焦点
+    SwitchPane,
+    /// 光标处的曲目在当前曲目之后播放
+    PlayNext,
+    /// 光标处的曲目加入队尾
+    Enqueue,
+    /// 立即播放光标处的曲目
+    PlaySelected,
+    /// 队列中光标处的曲目上移
+    QueueMoveUp,
+    /// 队列中光标处的曲目下移
+    QueueMoveDown,
+    /// 从队列中移除光标处的曲目
+    Dequeue,
+    /// 保存播放列表或待播队列
+    SavePlaylist,
+    /// 在当前位置添加书签, 先输入名称
+    AddBookmark,
+    /// 删除当前位置之前最近的书签
+    RemoveBookmark,
+    /// 跳到上一个书签
+    PrevBookmark,
+    /// 跳到下一个书签
+    NextBookmark,
+    /// 输入书签名称
+    PromptInput(char),
+    /// 删除书签名称的最后一个字符
+    PromptBackspace,
+    /// 确认添加书签
+    PromptConfirm,
+    /// 取消添加书签
+    PromptCancel,
 }
 /// 执行`Operation`变体对应的具体操作
 pub fn key_action(core: &mut PlayCore, op: Operation) -> AnyResult<()> {
     use Operation::*;
+    // 确保操作作用于正在播放的曲目
+    core.sync_track();
     match op {
         TogglePaused => {
-            if core.is_paused() {
+            // 列表已播完时从头播放
+            if core.is_finished() {
+                core.play_from_start()?;
+            } else if core.is_paused() {
                 core.play();
             } else {
                 core.pause();
             }
         }
         Next => {
-            switch(core, true);
-            core.playback()?;
+            // 不循环时在最后一首之后停止
+            if switch(core, true) {
+                core.playback()?;
+            } else {
+                core.finish();
+            }
         }
         Prev => {
             switch(core, false);
             core.playback()?;
         }
         Exit => {
+            core.save_session();
             core.stop();
             core.exit();
         }
@@ -52,26 +133,145 @@ pub fn key_action(core: &mut PlayCore, op: Operation) -> AnyResult<()> {
         Backward => {
             backward(core)?;
         }
+        VolumeUp => core.change_volume(VOLUME_STEP),
+        VolumeDown => core.change_volume(-VOLUME_STEP),
+        Mute => core.toggle_mute(),
+        SpeedUp => core.change_speed(SPEED_STEP),
+        SpeedDown => core.change_speed(-SPEED_STEP),
+        SpeedReset => core.speed.set(1.0),
+        CycleEq => core.cycle_eq(),
+        MarkA => core.mark_a(),
+        MarkB => core.mark_b()?,
+        ClearLoop => core.ab_loop = Default::default(),
+        CycleSleep => core.set_sleep(SleepMode::cycle(core.sleep.map(|s| s.mode))),
+        CycleShuffle => core.cycle_shuffle(),
+        CycleRepeat => core.cycle_repeat(),
+        ToggleQueueView => {
+            core.view = match core.view {
+                Some(_) => None,
+                None => Some(QueueView::new(core.playlist.current())),
+            };
+        }
+        CursorUp | CursorDown => {
+            let len = match core.view.as_ref().map(|v| v.pane) {
+                Some(Pane::Queue) => core.queue.len(),
+                _ => core.playlist.len(),
+            };
+            if let Some(view) = &mut core.view {
+                view.move_cursor(matches!(op, CursorUp), len);
+            }
+        }
+        SwitchPane => {
+            if let Some(view) = &mut core.view {
+                view.switch_pane();
+            }
+        }
+        PlayNext | Enqueue => {
+            if let Some(view) = &core.view
+                && view.pane == Pane::Playlist
+            {
+                core.enqueue(view.cursor, matches!(op, PlayNext));
+            }
+        }
+        PlaySelected => play_selected(core)?,
+        QueueMoveUp | QueueMoveDown => {
+            if let Some(view) = &mut core.view
+                && view.pane == Pane::Queue
+            {
+                let from = view.queue_cursor;
+                let to = match op {
+                    QueueMoveUp => from.saturating_sub(1),
+                    _ => from + 1,
+                };
+                if to < core.queue.len() {
+                    core.queue.move_entry(from, to);
+                    view.queue_cursor = to;
+                }
+            }
+        }
+        Dequeue => {
+            if let Some(view) = &core.view
+                && view.pane == Pane::Queue
+            {
+                core.queue.remove(view.queue_cursor);
+            }
+        }
+        SavePlaylist => {
+            let path = core.save_list()?;
+            core.message = Some(format!("已保存到{}", path.display()));
+        }
+        AddBookmark => core.start_bookmark(),
+        RemoveBookmark => core.remove_bookmark()?,
+        PrevBookmark => core.jump_bookmark(false)?,
+        NextBookmark => core.jump_bookmark(true)?,
+        PromptInput(c) => {
+            if let Some(prompt) = &mut core.prompt {
+                prompt.name.push(c);
+            }
+        }
+        PromptBackspace => {
+            if let Some(prompt) = &mut core.prompt {
+                prompt.name.pop();
+            }
+        }
+        PromptConfirm => core.add_bookmark()?,
+        PromptCancel => core.prompt = None,
+    }
+    // 队列变短后光标保持在范围内
+    if let Some(view) = &mut core.view {
+        view.clamp(core.playlist.len(), core.queue.len());
     }
     Ok(())
 }
-pub fn switch(core: &mut PlayCore, is_next: bool) {
-    match is_next {
-        true => {
-            if core.current_audio_idx == core.audio_total {
-                core.current_audio_idx = 1
-            } else {
-                core.current_audio_idx += 1;
-            }
+/// 立即播放视图中光标处的曲目. 队列中的曲目先移到队首, 按队列曲目播放
+fn play_selected(core: &mut PlayCore) -> AnyResult<()> {
+    let Some(view) = &core.view else {
+        return Ok(());
+    };
+    match view.pane {
+        Pane::Playlist => core.playlist.select(view.cursor),
+        Pane::Queue if view.queue_cursor < core.queue.len() => {
+            core.queue.move_entry(view.queue_cursor, 0);
+            switch(core, true);
         }
-        false => {
-            if core.current_audio_idx == 1 {
-                core.current_audio_idx = core.audio_total;
-            } else {
-                core.current_audio_idx -= 1;
-            }
+        Pane::Queue => return Ok(()),
+    }
+    core.playback()
+}
+/// 切换到上一首/下一首, 没有可切换的曲目时返回`false`
+pub fn switch(core: &mut PlayCore, is_next: bool) -> bool {
+    match next_index(core, is_next) {
+        Some(idx) => {
+            core.playlist.select(idx);
+            true
+        }
+        None => false,
+    }
+}
+/// 计算上一首/下一首的位置
+///
+/// 下一首优先取待播队列. 随机播放时按随机顺序, 没有可回退的历史时与顺序播放一样按列表
+/// 首尾循环; 正在播放队列时从原来的列表位置接续. 不循环时列表末尾(随机播放时为本轮末尾)
+/// 没有下一首, 返回`None`
+pub fn next_index(core: &mut PlayCore, is_next: bool) -> Option<usize> {
+    if is_next && let Some(idx) = core.queue.peek(&core.playlist) {
+        return Some(idx);
+    }
+    let base = core.queue.anchor(&core.playlist).unwrap_or(core.playlist.current());
+    let shuffle = core.shuffle.is_on();
+    if is_next && core.repeat == RepeatMode::Off {
+        let at_end = match shuffle {
+            true => core.shuffle.at_end(&core.playlist),
+            false => core.playlist.is_last(base),
+        };
+        if at_end {
+            return None;
         }
     }
+    if shuffle && let Some(idx) = core.shuffle.peek(is_next, &core.playlist, &core.library) {
+        return Some(idx);
+    }
+    Some(core.playlist.neighbor(base, is_next))
 }
 pub fn forward(core: &mut PlayCore) -> AnyResult<()> {
     let span = Duration::from_secs(5);
@@ -79,7 +279,7 @@ pub fn forward(core: &mut PlayCore) -> AnyResult<()> {
     if (0..core.src_time).contains(&target_pos.as_secs()) {
         core.seek(target_pos)?;
     } else {
-        let target_pos = Duration::from_secs(core.src_time - 1);
+        let target_pos = Duration::from_secs(core.src_time.saturating_sub(1));
         core.seek(target_pos)?;
     }
     Ok(())
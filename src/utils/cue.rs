@@ -0,0 +1,381 @@
+use crate::{TrackMetadata, is_audio, resolve_location};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// CUE表中的一条曲目
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueEntry {
+    /// 曲号
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// 起点, 即`INDEX 01`, 缺失时为`INDEX 00`
+    pub start: Duration,
+}
+
+/// CUE表中一个音频文件的内容
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+    /// `FILE`记录的文件名, 嵌入在音频文件中的CUE表可能没有
+    pub file: String,
+    /// 专辑名
+    pub title: Option<String>,
+    /// 专辑艺术家
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub tracks: Vec<CueEntry>,
+}
+
+/// 由CUE表拆分出的一首虚拟曲目, 对应音频文件中的一段
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueTrack {
+    /// 在音频文件中的起点
+    pub start: Duration,
+    /// 在音频文件中的终点, `None`表示到文件结尾
+    pub end: Option<Duration>,
+    pub metadata: TrackMetadata,
+}
+
+/// 解析中的音轨, 记录`INDEX 00`与`INDEX 01`
+struct PendingTrack {
+    entry: CueEntry,
+    pregap: Option<Duration>,
+    index: Option<Duration>,
+}
+
+/// 解析CUE表, 每个`FILE`对应一项
+///
+/// 只读取`AUDIO`音轨; `FILE`之前出现的音轨归入文件名为空的一项
+pub fn parse_cue(text: &str) -> Vec<CueSheet> {
+    // 专辑级的信息写在第一个`FILE`之前, 作用于全部文件
+    let mut album = CueSheet::default();
+    let mut sheets: Vec<CueSheet> = Vec::new();
+    let mut track: Option<PendingTrack> = None;
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let (command, rest) = split_word(line.trim());
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                finish_track(&mut sheets, &album, track.take());
+                sheets.push(CueSheet {
+                    file: file_name(rest),
+                    tracks: Vec::new(),
+                    ..album.clone()
+                });
+            }
+            "TRACK" => {
+                finish_track(&mut sheets, &album, track.take());
+                let (number, kind) = split_word(rest);
+                if kind.eq_ignore_ascii_case("AUDIO") {
+                    track = Some(PendingTrack {
+                        entry: CueEntry {
+                            number: number.parse().unwrap_or(0),
+                            ..Default::default()
+                        },
+                        pregap: None,
+                        index: None,
+                    });
+                }
+            }
+            "INDEX" => {
+                let (number, time) = split_word(rest);
+                if let (Some(track), Some(time)) = (&mut track, parse_cue_time(time)) {
+                    match number.parse::<u32>() {
+                        Ok(0) => track.pregap = Some(time),
+                        Ok(1) => track.index = Some(time),
+                        _ => {}
+                    }
+                }
+            }
+            "TITLE" | "PERFORMER" => {
+                let value = Some(unquote(rest)).filter(|v| !v.is_empty());
+                let is_title = command.eq_ignore_ascii_case("TITLE");
+                match (&mut track, sheets.last_mut()) {
+                    (Some(track), _) if is_title => track.entry.title = value,
+                    (Some(track), _) => track.entry.performer = value,
+                    // 专辑信息也可能写在`FILE`之后、第一个`TRACK`之前
+                    (None, sheet) => {
+                        for sheet in [Some(&mut album), sheet].into_iter().flatten() {
+                            match is_title {
+                                true => sheet.title = value.clone(),
+                                false => sheet.performer = value.clone(),
+                            }
+                        }
+                    }
+                }
+            }
+            "REM" if track.is_none() => {
+                let (key, value) = split_word(rest);
+                let value = unquote(value);
+                for sheet in [Some(&mut album), sheets.last_mut()].into_iter().flatten() {
+                    match key.to_ascii_uppercase().as_str() {
+                        "GENRE" => sheet.genre = Some(value.clone()),
+                        "DATE" => sheet.year = value.get(..4).and_then(|y| y.parse().ok()),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_track(&mut sheets, &album, track);
+    sheets.retain(|sheet| !sheet.tracks.is_empty());
+    sheets
+}
+
+/// 音轨解析完毕, 加入当前文件. 没有任何`INDEX`的音轨忽略
+fn finish_track(sheets: &mut Vec<CueSheet>, album: &CueSheet, track: Option<PendingTrack>) {
+    let Some(track) = track else {
+        return;
+    };
+    let Some(start) = track.index.or(track.pregap) else {
+        return;
+    };
+    if sheets.is_empty() {
+        sheets.push(album.clone());
+    }
+    if let Some(sheet) = sheets.last_mut() {
+        sheet.tracks.push(CueEntry {
+            start,
+            ..track.entry
+        });
+    }
+}
+
+impl CueSheet {
+    /// 拆分为虚拟曲目, 前一首的终点为后一首的起点.
+    /// CUE表中没有的字段取自整个文件的元数据`base`
+    pub fn split(&self, base: &TrackMetadata) -> Vec<CueTrack> {
+        let mut tracks = self.tracks.clone();
+        tracks.sort_by_key(|track| track.start);
+        let ends = tracks
+            .iter()
+            .skip(1)
+            .map(|next| Some(next.start))
+            .chain([None]);
+        tracks
+            .iter()
+            .zip(ends)
+            // 起点超出文件时长的是对不上的CUE表, 忽略
+            .filter(|(track, _)| base.duration.is_none_or(|total| track.start < total))
+            .map(|(track, end)| {
+                let duration = end
+                    .or(base.duration)
+                    .map(|end| end.saturating_sub(track.start));
+                let title = match &track.title {
+                    Some(title) => title.clone(),
+                    None => format!("音轨{:02}", track.number),
+                };
+                let metadata = TrackMetadata {
+                    title,
+                    artist: track
+                        .performer
+                        .clone()
+                        .or(self.performer.clone())
+                        .or(base.artist.clone()),
+                    album: self.title.clone().or(base.album.clone()),
+                    album_artist: self.performer.clone().or(base.album_artist.clone()),
+                    track_number: Some(track.number),
+                    year: self.year.or(base.year),
+                    genre: self.genre.clone().or(base.genre.clone()),
+                    duration,
+                    ..base.clone()
+                };
+                CueTrack {
+                    start: track.start,
+                    end,
+                    metadata,
+                }
+            })
+            .collect()
+    }
+}
+
+/// 把FLAC CUESHEET元数据块中的曲目起点转为CUE表文本, `starts`为曲号与起点
+pub fn cue_text(starts: &[(u32, Duration)]) -> String {
+    let mut text = String::new();
+    for (number, start) in starts {
+        // CUE表的时间以1/75秒为一帧
+        let frames = (start.as_secs_f64() * 75.0).round() as u64;
+        let (minutes, seconds, frames) = (frames / 75 / 60, frames / 75 % 60, frames % 75);
+        text.push_str(&format!(
+            "TRACK {number:02} AUDIO\n  INDEX 01 {minutes:02}:{seconds:02}:{frames:02}\n"
+        ));
+    }
+    text
+}
+
+/// 解析`分:秒:帧`形式的时间, 每秒75帧
+fn parse_cue_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|part| part.trim().parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    let frames = (minutes * 60 + seconds) * 75 + frames;
+    Some(Duration::from_secs_f64(frames as f64 / 75.0))
+}
+
+/// 拆出第一个词与其余部分
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// 去掉两端的引号
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    match text.strip_prefix('"').and_then(|rest| rest.split_once('"')) {
+        Some((inner, _)) => inner.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// `FILE`的文件名, 去掉其后的文件类型, 如`FILE "a b.flac" WAVE`中的`a b.flac`
+fn file_name(text: &str) -> String {
+    let text = text.trim();
+    match text.starts_with('"') {
+        true => unquote(text),
+        false => text
+            .rsplit_once(char::is_whitespace)
+            .map_or(text, |(name, _)| name.trim_end())
+            .to_string(),
+    }
+}
+
+/// 读取CUE表文件, 返回其中各文件对应的音频路径与内容
+///
+/// `FILE`记录的文件不存在时(如转换格式后扩展名变了), 取同目录下文件名相同的音频文件
+pub fn read_cue_file(path: &Path) -> Vec<(PathBuf, CueSheet)> {
+    let Ok(bytes) = fs::read(path) else {
+        return Vec::new();
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let cue_stem = path.file_stem().unwrap_or_default();
+    parse_cue(&String::from_utf8_lossy(&bytes))
+        .into_iter()
+        .filter_map(|sheet| {
+            let file = PathBuf::from(sheet.file.replace('\\', "/"));
+            let stem = match sheet.file.is_empty() {
+                true => cue_stem,
+                false => file.file_stem()?,
+            }
+            .to_os_string();
+            let audio = resolve_location(&sheet.file, dir)
+                .filter(|audio| is_audio(audio))
+                .or_else(|| {
+                    fs::read_dir(dir)
+                        .ok()?
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.path())
+                        .find(|p| is_audio(p) && p.file_stem() == Some(stem.as_os_str()))
+                })?;
+            Some((audio.canonicalize().unwrap_or(audio), sheet))
+        })
+        .collect()
+}
+
+/// 是否为CUE表文件
+pub fn is_cue(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE Rock
+REM DATE 1999/01/02
+PERFORMER \"The Band\"
+TITLE \"Live Album\"
+FILE \"Live Album.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Intro\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Song\"
+    PERFORMER \"Guest\"
+    INDEX 00 03:59:50
+    INDEX 01 04:00:00
+  TRACK 03 AUDIO
+    INDEX 00 07:30:00
+  TRACK 04 MODE1/2352
+    INDEX 01 09:00:00
+FILE disc2.wav WAVE
+  TRACK 05 AUDIO
+    INDEX 01 00:00:00
+";
+
+    #[test]
+    fn parse_files_tracks_and_indexes() {
+        let sheets = parse_cue(SHEET);
+        assert_eq!(sheets.len(), 2);
+        let sheet = &sheets[0];
+        assert_eq!(sheet.file, "Live Album.flac");
+        assert_eq!(sheet.title.as_deref(), Some("Live Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("The Band"));
+        assert_eq!(sheet.genre.as_deref(), Some("Rock"));
+        assert_eq!(sheet.year, Some(1999));
+        // 数据音轨忽略
+        assert_eq!(sheet.tracks.len(), 3);
+        // 有`INDEX 01`时以其为起点, 否则取`INDEX 00`
+        assert_eq!(sheet.tracks[1].start, Duration::from_secs(240));
+        assert_eq!(sheet.tracks[1].performer.as_deref(), Some("Guest"));
+        assert_eq!(sheet.tracks[2].start, Duration::from_secs(450));
+        assert_eq!(sheet.tracks[2].title, None);
+        assert_eq!(sheets[1].file, "disc2.wav");
+        assert_eq!(sheets[1].title.as_deref(), Some("Live Album"));
+    }
+
+    #[test]
+    fn split_into_ranges() {
+        let base = TrackMetadata {
+            duration: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        let tracks = parse_cue(SHEET)[0].split(&base);
+        let ranges: Vec<_> = tracks.iter().map(|t| (t.start.as_secs(), t.end)).collect();
+        assert_eq!(
+            ranges,
+            [
+                (0, Some(Duration::from_secs(240))),
+                (240, Some(Duration::from_secs(450))),
+                (450, None)
+            ]
+        );
+        assert_eq!(tracks[2].metadata.duration, Some(Duration::from_secs(150)));
+        assert_eq!(tracks[2].metadata.title, "音轨03");
+        assert_eq!(tracks[0].metadata.artist.as_deref(), Some("The Band"));
+        assert_eq!(tracks[1].metadata.artist.as_deref(), Some("Guest"));
+    }
+
+    #[test]
+    fn cue_time_has_75_frames_per_second() {
+        assert_eq!(parse_cue_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_cue_time("01:02:00"), Some(Duration::from_secs(62)));
+        assert_eq!(
+            parse_cue_time("00:01:74"),
+            Some(Duration::from_secs_f64(1.0 + 74.0 / 75.0))
+        );
+        assert_eq!(parse_cue_time("00:01"), None);
+        assert_eq!(parse_cue_time("aa:01:02"), None);
+        // 与生成的CUE表文本互逆
+        let start = Duration::from_secs_f64(61.0 + 30.0 / 75.0);
+        let text = cue_text(&[(1, start)]);
+        let time = text.split_whitespace().last().unwrap();
+        assert_eq!(time, "01:01:30");
+        assert_eq!(parse_cue_time(time), Some(start));
+    }
+
+    #[test]
+    fn file_name_strips_quotes_and_type() {
+        assert_eq!(file_name("\"a b.flac\" WAVE"), "a b.flac");
+        assert_eq!(file_name("a b.flac WAVE"), "a b.flac");
+        assert_eq!(file_name("album.wav"), "album.wav");
+        assert_eq!(file_name("  \"x.ape\"  "), "x.ape");
+    }
+}
@@ -0,0 +1,534 @@
+use crate::{
+    AnyResult, CueSheet, CueTrack, PlaylistItem, TrackMetadata, anyhow, is_audio, is_cue,
+    parse_cue, probe_file, read_cue_file,
+};
+use directories::ProjectDirs;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use walkdir::WalkDir;
+
+/// 索引文件名
+const LIBRARY_FILE: &str = "library.bin";
+/// 索引文件头
+const MAGIC: &[u8; 4] = b"MMLB";
+/// 索引格式版本, 格式变化时递增, 旧索引将被丢弃
+const VERSION: u32 = 3;
+/// 索引中单个字符串的最大字节数, 超出时视为索引已损坏
+const MAX_FIELD_LEN: u32 = 16 * 1024 * 1024;
+
+/// 音乐库中的一个文件
+#[derive(Clone, Debug)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    /// 修改时间(自UNIX纪元的纳秒数)
+    pub mtime: u64,
+    pub size: u64,
+    /// 首次加入音乐库的时间(自UNIX纪元的秒数)
+    pub added: u64,
+    /// 播放次数
+    pub play_count: u32,
+    pub metadata: TrackMetadata,
+    /// 嵌入的CUE表文本
+    pub cue_sheet: Option<String>,
+}
+
+/// 持久化的音乐库索引
+///
+/// 保存在数据目录的`library.bin`中, 记录文件的修改时间、大小和元数据.
+/// 重新扫描时只探测新增或变化的文件, 并清除已不存在的条目
+#[derive(Default)]
+pub struct Library {
+    entries: HashMap<PathBuf, LibraryEntry>,
+    /// 补充播放列表信息之前的元数据, 保存索引时写入这些原始值
+    unhinted: HashMap<PathBuf, TrackMetadata>,
+    /// 音频文件旁的CUE表, 以音频文件为键, 不写入索引
+    cue_sheets: HashMap<PathBuf, CueSheet>,
+    /// 已读取过CUE表的目录
+    cue_dirs: HashSet<PathBuf>,
+}
+
+impl Library {
+    /// 读取索引文件, 不存在或无法读取时为空
+    pub fn open() -> Self {
+        let entries = Library::file_path()
+            .and_then(|path| fs::File::open(path).ok())
+            .and_then(|file| read_entries(&mut BufReader::new(file)).ok())
+            .unwrap_or_default();
+        Self {
+            entries: entries.into_iter().map(|e| (e.path.clone(), e)).collect(),
+            unhinted: HashMap::new(),
+            cue_sheets: HashMap::new(),
+            cue_dirs: HashSet::new(),
+        }
+    }
+
+    /// 索引文件路径, 位于数据目录(如`~/.local/share/mini-music`)
+    fn file_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "mini-music").map(|dirs| dirs.data_dir().join(LIBRARY_FILE))
+    }
+
+    /// 写入索引文件
+    pub fn save(&self) -> AnyResult<()> {
+        let path = Library::file_path().ok_or(anyhow!("找不到数据目录"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // 先写临时文件再替换, 避免中断时损坏索引
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp)?);
+        let entries: Vec<LibraryEntry> = self
+            .entries
+            .values()
+            .map(|entry| match self.unhinted.get(&entry.path) {
+                Some(metadata) => LibraryEntry {
+                    metadata: metadata.clone(),
+                    ..entry.clone()
+                },
+                None => entry.clone(),
+            })
+            .collect();
+        write_entries(&mut writer, entries.iter())?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// 扫描目录并更新索引, 按遍历顺序返回其中的音频文件
+    ///
+    /// 修改时间和大小未变的文件沿用索引中的元数据, `rescan`为`true`时全部重新探测.
+    /// 目录下已不存在的条目从索引中清除
+    pub fn scan(&mut self, dir: &Path, rescan: bool) -> Vec<PathBuf> {
+        // 以绝对路径为键, 与启动时的工作目录无关
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let mut found = Vec::new();
+        let mut cues = Vec::new();
+        let mut probed = 0;
+        for entry in WalkDir::new(&dir).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if entry.file_type().is_file() && is_cue(path) {
+                cues.push(path.to_path_buf());
+                continue;
+            }
+            if !entry.file_type().is_file() || !is_audio(path) {
+                continue;
+            }
+            let Ok(file) = entry.metadata() else {
+                continue;
+            };
+            if self.index(path, &file, rescan) {
+                probed += 1;
+                if probed % 500 == 0 {
+                    println!("已扫描{probed}个文件...");
+                }
+            }
+            found.push(path.to_path_buf());
+        }
+        // 清除该目录下已不存在的文件
+        let present: HashSet<&PathBuf> = found.iter().collect();
+        self.entries
+            .retain(|path, _| !path.starts_with(&dir) || present.contains(path));
+        self.cue_sheets.retain(|path, _| !path.starts_with(&dir));
+        self.cue_dirs.retain(|cue_dir| !cue_dir.starts_with(&dir));
+        for cue in cues {
+            self.cue_sheets.extend(read_cue_file(&cue));
+            self.cue_dirs.extend(cue.parent().map(Path::to_path_buf));
+        }
+        found
+    }
+
+    /// 更新单个文件的索引, 同目录下的CUE表每个目录只读取一次
+    pub fn update(&mut self, path: &Path) {
+        if let Ok(file) = fs::metadata(path) {
+            self.index(path, &file, false);
+        }
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if !self.cue_dirs.insert(dir.to_path_buf()) {
+            return;
+        }
+        let Ok(files) = fs::read_dir(dir) else {
+            return;
+        };
+        for cue in files.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if is_cue(&cue) {
+                self.cue_sheets.extend(read_cue_file(&cue));
+            }
+        }
+    }
+
+    /// 文件按CUE表拆分出的曲目, 旁边的CUE表优先于嵌入的. 没有CUE表时为空
+    pub fn cue_tracks(&self, path: &Path) -> Vec<CueTrack> {
+        let Some(entry) = self.entries.get(path) else {
+            return Vec::new();
+        };
+        let embedded = || {
+            let text = entry.cue_sheet.as_deref()?;
+            parse_cue(text).into_iter().next()
+        };
+        let metadata = self.unhinted.get(path).unwrap_or(&entry.metadata);
+        match self.cue_sheets.get(path).cloned().or_else(embedded) {
+            Some(sheet) => sheet.split(metadata),
+            None => Vec::new(),
+        }
+    }
+
+    /// 移除文件, 或目录下全部文件的索引
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.retain(|entry, _| !entry.starts_with(path));
+        self.cue_dirs.retain(|dir| !dir.starts_with(path));
+    }
+
+    /// 文件为新增或已变化时(或`force`为`true`时)探测元数据并写入索引, 返回是否探测
+    fn index(&mut self, path: &Path, file: &fs::Metadata, force: bool) -> bool {
+        let mtime = file
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as u64);
+        let size = file.len();
+        let cached = self.entries.get(path);
+        if !force && cached.is_some_and(|e| e.mtime == mtime && e.size == size) {
+            return false;
+        }
+        let (added, play_count) = cached.map_or_else(
+            || {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                (now.as_secs(), 0)
+            },
+            |e| (e.added, e.play_count),
+        );
+        let probed = probe_file(path).ok();
+        let metadata = TrackMetadata::new(path, probed.as_ref());
+        let cue_sheet = probed.and_then(|probed| probed.cue_sheet);
+        self.unhinted.remove(path);
+        self.entries.insert(
+            path.to_path_buf(),
+            LibraryEntry {
+                path: path.to_path_buf(),
+                mtime,
+                size,
+                added,
+                play_count,
+                metadata,
+                cue_sheet,
+            },
+        );
+        true
+    }
+
+    /// 记录一次播放
+    pub fn record_play(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.play_count += 1;
+        }
+    }
+
+    /// 把播放列表文件记录的信息补充到标签缺失的字段, 只在本次运行中有效
+    pub fn apply_hint(&mut self, hint: &PlaylistItem) {
+        if let Some(entry) = self.entries.get_mut(&hint.path) {
+            self.unhinted
+                .entry(hint.path.clone())
+                .or_insert_with(|| entry.metadata.clone());
+            entry.metadata.apply_hint(&hint.path, hint);
+        }
+    }
+
+    /// 查询文件的索引条目
+    pub fn get(&self, path: &Path) -> Option<&LibraryEntry> {
+        self.entries.get(path)
+    }
+}
+
+fn write_entries<'a, W: Write>(
+    w: &mut W,
+    entries: impl ExactSizeIterator<Item = &'a LibraryEntry>,
+) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_u32(w, VERSION)?;
+    write_u64(w, entries.len() as u64)?;
+    for entry in entries {
+        write_path(w, &entry.path)?;
+        write_u64(w, entry.mtime)?;
+        write_u64(w, entry.size)?;
+        write_u64(w, entry.added)?;
+        write_u32(w, entry.play_count)?;
+        let meta = &entry.metadata;
+        write_str(w, &meta.title)?;
+        for text in [
+            &meta.artist,
+            &meta.album,
+            &meta.album_artist,
+            &meta.genre,
+            &meta.codec,
+        ] {
+            write_opt(w, text.as_deref(), |w, v| write_str(w, v))?;
+        }
+        for number in [
+            meta.track_number,
+            meta.disc_number,
+            meta.year,
+            meta.bitrate,
+            meta.sample_rate,
+        ] {
+            write_opt(w, number, write_u32)?;
+        }
+        write_opt(w, meta.channels.map(u32::from), write_u32)?;
+        write_opt(w, meta.rating.map(f32::to_bits), write_u32)?;
+        write_opt(w, meta.duration.map(|d| d.as_millis() as u64), write_u64)?;
+        write_opt(w, entry.cue_sheet.as_deref(), |w, v| write_str(w, v))?;
+    }
+    Ok(())
+}
+
+fn read_entries<R: Read>(r: &mut R) -> io::Result<Vec<LibraryEntry>> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(r)? != VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "索引格式不兼容"));
+    }
+    let count = read_u64(r)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let path = read_path(r)?;
+        let mtime = read_u64(r)?;
+        let size = read_u64(r)?;
+        let added = read_u64(r)?;
+        let play_count = read_u32(r)?;
+        let mut meta = TrackMetadata {
+            title: read_str(r)?,
+            ..Default::default()
+        };
+        for text in [
+            &mut meta.artist,
+            &mut meta.album,
+            &mut meta.album_artist,
+            &mut meta.genre,
+            &mut meta.codec,
+        ] {
+            *text = read_opt(r, read_str)?;
+        }
+        for number in [
+            &mut meta.track_number,
+            &mut meta.disc_number,
+            &mut meta.year,
+            &mut meta.bitrate,
+            &mut meta.sample_rate,
+        ] {
+            *number = read_opt(r, read_u32)?;
+        }
+        meta.channels = read_opt(r, read_u32)?.map(|n| n as u16);
+        meta.rating = read_opt(r, read_u32)?.map(f32::from_bits);
+        meta.duration = read_opt(r, read_u64)?.map(Duration::from_millis);
+        let cue_sheet = read_opt(r, read_str)?;
+        entries.push(LibraryEntry {
+            path,
+            mtime,
+            size,
+            added,
+            play_count,
+            metadata: meta,
+            cue_sheet,
+        });
+    }
+    Ok(entries)
+}
+
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+fn write_str<W: Write>(w: &mut W, v: &str) -> io::Result<()> {
+    write_bytes(w, v.as_bytes())
+}
+fn write_bytes<W: Write>(w: &mut W, v: &[u8]) -> io::Result<()> {
+    write_u32(w, v.len() as u32)?;
+    w.write_all(v)
+}
+/// 路径按系统原始字节保存, 非UTF-8的文件名也能原样读回
+#[cfg(unix)]
+fn write_path<W: Write>(w: &mut W, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    write_bytes(w, path.as_os_str().as_bytes())
+}
+#[cfg(not(unix))]
+fn write_path<W: Write>(w: &mut W, path: &Path) -> io::Result<()> {
+    write_str(w, &path.to_string_lossy())
+}
+/// 可选值以一个字节标记是否存在
+fn write_opt<W: Write, T>(
+    w: &mut W,
+    v: Option<T>,
+    write: impl Fn(&mut W, T) -> io::Result<()>,
+) -> io::Result<()> {
+    match v {
+        Some(v) => {
+            w.write_all(&[1])?;
+            write(w, v)
+        }
+        None => w.write_all(&[0]),
+    }
+}
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+fn read_str<R: Read>(r: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(r)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+/// 读取带长度的字节串, 长度超出上限或文件提前结束时为错误
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(r)?;
+    if len > MAX_FIELD_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "索引已损坏"));
+    }
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+#[cfg(unix)]
+fn read_path<R: Read>(r: &mut R) -> io::Result<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+    Ok(PathBuf::from(OsString::from_vec(read_bytes(r)?)))
+}
+#[cfg(not(unix))]
+fn read_path<R: Read>(r: &mut R) -> io::Result<PathBuf> {
+    read_str(r).map(PathBuf::from)
+}
+fn read_opt<R: Read, T>(
+    r: &mut R,
+    read: impl Fn(&mut R) -> io::Result<T>,
+) -> io::Result<Option<T>> {
+    let mut flag = [0];
+    r.read_exact(&mut flag)?;
+    match flag[0] {
+        0 => Ok(None),
+        _ => read(r).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<LibraryEntry> {
+        let full = LibraryEntry {
+            path: PathBuf::from("/music/专辑/01 - 歌.flac"),
+            mtime: 1_700_000_000_123_456_789,
+            size: 12_345_678,
+            added: 1_700_000_000,
+            play_count: 7,
+            metadata: TrackMetadata {
+                title: "歌".to_string(),
+                artist: Some("歌手".to_string()),
+                album: Some("专辑".to_string()),
+                album_artist: Some("Various".to_string()),
+                track_number: Some(1),
+                disc_number: Some(2),
+                year: Some(2024),
+                genre: Some("Pop".to_string()),
+                rating: Some(0.8),
+                duration: Some(Duration::from_millis(234_567)),
+                bitrate: Some(1411),
+                sample_rate: Some(44100),
+                channels: Some(2),
+                codec: Some("FLAC".to_string()),
+            },
+            cue_sheet: Some("FILE \"a.flac\" WAVE\n".to_string()),
+        };
+        let bare = LibraryEntry {
+            path: PathBuf::from("/music/bare.mp3"),
+            mtime: 0,
+            size: 0,
+            added: 0,
+            play_count: 0,
+            metadata: TrackMetadata {
+                title: "bare".to_string(),
+                ..Default::default()
+            },
+            cue_sheet: None,
+        };
+        vec![full, bare]
+    }
+
+    fn encode(entries: &[LibraryEntry]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_entries(&mut buf, entries.iter()).unwrap();
+        buf
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entries = entries();
+        let read = read_entries(&mut encode(&entries).as_slice()).unwrap();
+        assert_eq!(read.len(), entries.len());
+        for (a, b) in read.iter().zip(&entries) {
+            assert_eq!(a.path, b.path);
+            assert_eq!(
+                (a.mtime, a.size, a.added, a.play_count),
+                (b.mtime, b.size, b.added, b.play_count)
+            );
+            assert_eq!(a.metadata, b.metadata);
+            assert_eq!(a.cue_sheet, b.cue_sheet);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path_round_trips() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let mut entries = entries();
+        entries[1].path = Path::new("/music").join(OsStr::from_bytes(b"\xff\xfe.mp3"));
+        let read = read_entries(&mut encode(&entries).as_slice()).unwrap();
+        assert_eq!(read[1].path, entries[1].path);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let buf = encode(&entries());
+        for len in 0..buf.len() {
+            assert!(read_entries(&mut &buf[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn corrupt_file_is_an_error() {
+        let buf = encode(&entries());
+        let kind = |buf: &[u8]| read_entries(&mut &buf[..]).unwrap_err().kind();
+        let mut magic = buf.clone();
+        magic[0] = b'X';
+        assert_eq!(kind(&magic), io::ErrorKind::InvalidData);
+        let mut version = buf.clone();
+        version[4] = 99;
+        assert_eq!(kind(&version), io::ErrorKind::InvalidData);
+        // 第一条路径的长度被改成超出上限的值, 不会按该长度分配内存
+        let mut length = buf.clone();
+        length[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(kind(&length), io::ErrorKind::InvalidData);
+        // 条目数多于实际内容
+        let mut count = buf;
+        count[8..16].copy_from_slice(&3u64.to_le_bytes());
+        assert_eq!(kind(&count), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,65 +1,110 @@
 use crate::{AnyResult, anyhow};
-use regex::Regex;
-use std::{
-    fs::{self, File},
-    path::Path, time::Duration,
-};
-use symphonia::core::{
-    formats::FormatOptions, io::MediaSourceStream, meta::StandardTagKey, probe::Hint,
-};
-
-///  加载并解析一个音频文件的歌词
-pub fn load_and_parse_lrc(path: &Path) -> Option<Vec<(Duration, String)>> {
-    match get_lyrics(path) {
-        Ok(lrc_string) => {
-            let parsed = parse_lrc(&lrc_string);
-            if parsed.is_empty() {
-                None
-            } else {
-                Some(parsed)
-            }
-        }
-        Err(_) => {
-            // 未找到元数据
-            // println!("{:?}", e);
-            None
+use regex::{Captures, Regex};
+use std::{fs, path::Path, sync::LazyLock, time::Duration};
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+/// 歌词时间戳`[mm:ss.xx]`, 一行可以有多个. 不包含后面的文本部分
+static TIMESTAMP_REX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(\d{2}):(\d{2})[.:](\d{2,3})\]").unwrap());
+/// 增强LRC行内的逐字时间`<mm:ss.xx>`
+static WORD_REX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(\d{2}):(\d{2})[.:](\d{2,3})>").unwrap());
+/// `[ti:标题]`等标识标签
+static TAG_REX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[([A-Za-z]+):(.*)\]$").unwrap());
+
+/// 解析后的歌词文件, 含`[ti:]`等标识标签
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LyricsDocument {
+    /// `[ti:]`标题
+    pub title: Option<String>,
+    /// `[ar:]`艺术家
+    pub artist: Option<String>,
+    /// `[al:]`专辑
+    pub album: Option<String>,
+    /// `[length:]`歌曲时长
+    pub length: Option<Duration>,
+    /// `[offset:]`时间偏移(毫秒), 正数使歌词提前. 已应用到`lines`的全部时间
+    pub offset: i64,
+    /// 按时间排列的歌词
+    pub lines: Vec<LyricLine>,
+}
+
+/// 一行歌词
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    pub time: Duration,
+    /// 去掉逐字时间标签后的文本
+    pub text: String,
+    /// 增强LRC的逐字时间, 拼接起来即`text`. 普通歌词为空
+    pub words: Vec<LyricWord>,
+    /// 行尾标签给出的结束时间, 没有时到下一行开始为止
+    pub end: Option<Duration>,
+}
+
+/// 增强LRC中带开始时间的一个词
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricWord {
+    pub time: Duration,
+    pub text: String,
+}
+
+impl LyricLine {
+    /// 对行与各词的时间做同样的变换
+    pub fn map_times(mut self, f: impl Fn(Duration) -> Duration) -> Self {
+        self.time = f(self.time);
+        self.end = self.end.map(&f);
+        for word in &mut self.words {
+            word.time = f(word.time);
         }
+        self
+    }
+
+    /// 播放到`pos`时已唱完的字符数, 正在唱的词按时间比例计入. `next`为下一行的时间
+    pub fn sung_chars(&self, pos: Duration, next: Option<Duration>) -> usize {
+        let ends = self
+            .words
+            .iter()
+            .skip(1)
+            .map(|word| Some(word.time))
+            .chain([self.end.or(next)]);
+        self.words
+            .iter()
+            .zip(ends)
+            .map(|(word, end)| {
+                let chars = word.text.chars().count();
+                match end {
+                    _ if pos < word.time => 0,
+                    Some(end) if pos < end => {
+                        let progress =
+                            (pos - word.time).as_secs_f64() / (end - word.time).as_secs_f64();
+                        (chars as f64 * progress) as usize
+                    }
+                    _ => chars,
+                }
+            })
+            .sum()
     }
 }
 
-/// 从音频文件元数据或本地`.lrc`文件提取歌词
-fn get_lyrics(path: &Path) -> AnyResult<String> {
-    // 1. 创建媒体源流
-    let src = File::open(path)?;
-    let mss = MediaSourceStream::new(Box::new(src), Default::default());
-    // 2. 探测格式
-    // 创建一个 Hint 来帮助探测。如果文件有扩展名，这会很有用。
-    let mut hint = Hint::new();
-    if let Some(extension) = path.extension().and_then(|s| s.to_str()) {
-        hint.with_extension(extension);
-    }
-    //
-    // 默认的探测选项和元数据选项
-    let format_opts: FormatOptions = Default::default();
-    //
-    let metadata_opts = Default::default();
-
-    // 探测媒体源的格式
-    let probed =
-        symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
-
-    let mut format = probed.format;
-
-    // 3. 访问元数据, 遍历tag, 提取lyrics.或者从本地`.lrc`文件提取歌词
-    match format.metadata().current() {
-        Some(metsdata_rev) => {
-            let mut tags = metsdata_rev.tags().iter();
-            if let Some(tag_lrc) = tags.find(|tag| tag.std_key == Some(StandardTagKey::Lyrics)) {
-                Ok(tag_lrc.value.to_string())
-            } else {
-                get_local_lrc(path)
-            }
-        }
+///  加载并解析一个音频文件的歌词, `tags`为该文件的元数据标签
+pub fn load_and_parse_lrc(path: &Path, tags: &[Tag]) -> Option<LyricsDocument> {
+    let lrc_string = get_lyrics(path, tags).ok()?;
+    let document = parse_lrc(&lrc_string);
+    // 没有歌词也没有标识标签时视为未找到歌词
+    if document == LyricsDocument::default() {
+        None
+    } else {
+        Some(document)
+    }
+}
+
+/// 从音频文件元数据标签或本地`.lrc`文件提取歌词
+fn get_lyrics(path: &Path, tags: &[Tag]) -> AnyResult<String> {
+    match tags
+        .iter()
+        .find(|tag| tag.std_key == Some(StandardTagKey::Lyrics))
+    {
+        Some(tag_lrc) => Ok(tag_lrc.value.to_string()),
         None => get_local_lrc(path),
     }
 }
@@ -74,55 +119,277 @@ fn get_local_lrc(path: &Path) -> AnyResult<String> {
         Err(anyhow!("未找到歌词"))
     }
 }
-/// 解析LRC歌词文本
-
-fn parse_lrc(lrc_text: &str) -> Vec<(Duration, String)> {
-    // 这个正则表达式只用于匹配和捕获一个时间戳, 不包含后面的文本部分
-    let timestamp_rex = Regex::new(r"\[(\d{2}):(\d{2})[.:](\d{2,3})\]").unwrap();
+/// 解析LRC歌词文本, 支持增强LRC(A2)行内的`<mm:ss.xx>`逐字时间
+/// 与`[ti:]`、`[ar:]`、`[al:]`、`[length:]`、`[offset:]`标识标签
+fn parse_lrc(lrc_text: &str) -> LyricsDocument {
+    let mut document = LyricsDocument::default();
     let mut lyrics = Vec::new();
 
     for line in lrc_text.lines() {
         // 1. 找出当前行所有的歌词时间戳
         // 使用 captures_iter 来迭代所有匹配项
-        let timestamps: Vec<Duration> = timestamp_rex
+        let timestamps: Vec<Duration> = TIMESTAMP_REX
             .captures_iter(line)
-            .filter_map(|caps| {
-                // 解析分钟、秒和毫秒
-                let minutes: u64 = caps.get(1)?.as_str().parse().ok()?;
-                let seconds: u64 = caps.get(2)?.as_str().parse().ok()?;
-                let millis_str = caps.get(3)?.as_str();
-                let millis: u64 = if millis_str.len() == 2 {
-                    // 如果是厘秒 (xx)，则乘以10转为毫秒
-                    millis_str.parse().unwrap_or(0) * 10
-                } else {
-                    // 否则直接解析毫秒 (xxx)
-                    millis_str.parse().unwrap_or(0)
-                };
-                Some(
-                    Duration::from_millis(
-                        
-                        minutes * 60 * 1000 + seconds * 1000 + millis
-                    )
-                )
-            })
+            .filter_map(|caps| parse_timestamp(&caps))
             .collect();
-        // 如果该行没有任何有效的时间戳 (例如元数据行 [ar: artist]) 则跳过
-        if timestamps.is_empty() {
+        // 没有任何有效的时间戳的行 (例如元数据行 [ar: artist]) 只读取标识标签
+        let Some(&first) = timestamps.first() else {
+            if let Some(caps) = TAG_REX.captures(line.trim()) {
+                read_id_tag(&mut document, &caps[1], caps[2].trim());
+            }
             continue;
-        }
+        };
         // 2. 获取歌词文本
         // 文本是最后一个时间戳 `]` 之后的所有内容
         if let Some(last_bracket_pos) = line.rfind(']') {
-            let text = line[last_bracket_pos + 1..].trim().to_string();
+            let (words, end) = parse_words(&line[last_bracket_pos + 1..], first);
+            let text: String = words.iter().map(|word| word.text.as_str()).collect();
             // 3. 为每个时间戳创建一条歌词记录
             if !text.is_empty() {
+                let line = LyricLine {
+                    time: first,
+                    text,
+                    // 没有逐字时间时只有一个词, 按普通歌词显示
+                    words: if words.len() > 1 || end.is_some() {
+                        words
+                    } else {
+                        Vec::new()
+                    },
+                    end,
+                };
                 for time in timestamps {
-                    // text.clone() 是必需的，因为文本内容需要在多个元组中共享
-                    lyrics.push((time, text.clone()));
+                    // 同一行歌词出现多次时, 逐字时间按各自的行时间平移
+                    lyrics.push(line.clone().map_times(|t| (t + time).saturating_sub(first)));
                 }
             }
         }
     }
-    lyrics.sort_by(|a, b| a.0.cmp(&b.0));
-    lyrics
+    lyrics.sort_by_key(|line| line.time);
+    // 偏移作用于全部时间戳, 提前到0之前的歌词从0开始
+    let offset = Duration::from_millis(document.offset.unsigned_abs());
+    document.lines = match document.offset {
+        0 => lyrics,
+        ms if ms > 0 => lyrics
+            .into_iter()
+            .map(|line| line.map_times(|time| time.saturating_sub(offset)))
+            .collect(),
+        _ => lyrics
+            .into_iter()
+            .map(|line| line.map_times(|time| time + offset))
+            .collect(),
+    };
+    document
+}
+
+/// 读取一个标识标签, 不认识的标签与空值忽略
+fn read_id_tag(document: &mut LyricsDocument, key: &str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    match key.to_ascii_lowercase().as_str() {
+        "ti" => document.title = Some(value.to_string()),
+        "ar" => document.artist = Some(value.to_string()),
+        "al" => document.album = Some(value.to_string()),
+        "length" => document.length = parse_length(value),
+        "offset" => document.offset = value.parse().unwrap_or(0),
+        _ => {}
+    }
+}
+
+/// 解析`[length:]`的`mm:ss`或`mm:ss.xx`
+fn parse_length(value: &str) -> Option<Duration> {
+    let (minutes, seconds) = value.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    Duration::try_from_secs_f64(minutes as f64 * 60.0 + seconds).ok()
+}
+
+/// 从时间戳的捕获组解析时间
+fn parse_timestamp(caps: &Captures) -> Option<Duration> {
+    // 解析分钟、秒和毫秒
+    let minutes: u64 = caps.get(1)?.as_str().parse().ok()?;
+    let seconds: u64 = caps.get(2)?.as_str().parse().ok()?;
+    let millis_str = caps.get(3)?.as_str();
+    let millis: u64 = if millis_str.len() == 2 {
+        // 如果是厘秒 (xx)，则乘以10转为毫秒
+        millis_str.parse().unwrap_or(0) * 10
+    } else {
+        // 否则直接解析毫秒 (xxx)
+        millis_str.parse().unwrap_or(0)
+    };
+    Some(Duration::from_millis(
+        minutes * 60 * 1000 + seconds * 1000 + millis,
+    ))
+}
+
+/// 按`<mm:ss.xx>`标签拆分一行歌词, 返回各词与行尾标签给出的结束时间
+///
+/// 第一个标签之前的文本从行时间开始; 首尾空白去掉, 词间空白保留在前一个词中
+fn parse_words(text: &str, line_time: Duration) -> (Vec<LyricWord>, Option<Duration>) {
+    let mut words = Vec::new();
+    let (mut time, mut last) = (line_time, 0);
+    let mut tagged = false;
+    for caps in WORD_REX.captures_iter(text) {
+        let (Some(tag), Some(tag_time)) = (caps.get(0), parse_timestamp(&caps)) else {
+            continue;
+        };
+        words.push(LyricWord {
+            time,
+            text: text[last..tag.start()].to_string(),
+        });
+        (time, last, tagged) = (tag_time, tag.end(), true);
+    }
+    // 最后一个标签之后没有文本时, 该标签是最后一个词的结束时间
+    let tail = text[last..].trim_end();
+    let end = match tail.is_empty() && tagged {
+        true => Some(time),
+        false => {
+            words.push(LyricWord {
+                time,
+                text: tail.to_string(),
+            });
+            None
+        }
+    };
+    // 去掉开头的空白词与首尾空白
+    while words
+        .first()
+        .is_some_and(|word| word.text.trim().is_empty())
+    {
+        words.remove(0);
+    }
+    if let Some(word) = words.first_mut() {
+        word.text = word.text.trim_start().to_string();
+    }
+    if let Some(word) = words.last_mut() {
+        word.text = word.text.trim_end().to_string();
+    }
+    words.retain(|word| !word.text.is_empty());
+    (words, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn word(millis: u64, text: &str) -> LyricWord {
+        LyricWord {
+            time: ms(millis),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn words_split_at_time_tags() {
+        let (words, end) = parse_words("<00:01.00>Hello <00:01.50>big <00:02.250>world", ms(1000));
+        assert_eq!(
+            words,
+            [
+                word(1000, "Hello "),
+                word(1500, "big "),
+                word(2250, "world")
+            ]
+        );
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn trailing_tag_gives_end() {
+        let (words, end) = parse_words(" <00:01.00>你<00:01.40>好<00:02.00> ", ms(1000));
+        assert_eq!(words, [word(1000, "你"), word(1400, "好")]);
+        assert_eq!(end, Some(ms(2000)));
+    }
+
+    #[test]
+    fn leading_untagged_text_starts_at_line_time() {
+        let (words, end) = parse_words("Oh <00:05.00>yeah", ms(4000));
+        assert_eq!(words, [word(4000, "Oh "), word(5000, "yeah")]);
+        assert_eq!(end, None);
+        // 没有标签的普通歌词只有一个词
+        let (words, _) = parse_words("  plain line ", ms(4000));
+        assert_eq!(words, [word(4000, "plain line")]);
+    }
+
+    #[test]
+    fn repeated_line_shifts_word_times() {
+        let document = parse_lrc("[00:10.00][00:20.00]<00:10.00>a<00:10.50>b<00:11.00>\n");
+        assert_eq!(document.lines.len(), 2);
+        let second = &document.lines[1];
+        assert_eq!(second.time, ms(20000));
+        assert_eq!(second.words, [word(20000, "a"), word(20500, "b")]);
+        assert_eq!(second.end, Some(ms(21000)));
+        assert_eq!(document.lines[0].words[1], word(10500, "b"));
+    }
+
+    #[test]
+    fn plain_lines_have_no_words() {
+        let document = parse_lrc("[00:01.00]plain\n[00:02.00]\n");
+        assert_eq!(document.lines.len(), 1);
+        assert!(document.lines[0].words.is_empty());
+        assert_eq!(document.lines[0].sung_chars(ms(5000), None), 0);
+    }
+
+    #[test]
+    fn sung_chars_counts_partial_words() {
+        let document = parse_lrc("[00:01.00]<00:01.00>abcd<00:02.00>ef<00:03.00>\n");
+        let line = &document.lines[0];
+        assert_eq!(line.sung_chars(ms(500), None), 0);
+        assert_eq!(line.sung_chars(ms(1500), None), 2);
+        assert_eq!(line.sung_chars(ms(2000), None), 4);
+        assert_eq!(line.sung_chars(ms(2500), None), 5);
+        assert_eq!(line.sung_chars(ms(3000), None), 6);
+    }
+
+    #[test]
+    fn last_word_runs_until_next_line() {
+        let document = parse_lrc("[00:01.00]<00:01.00>ab<00:02.00>cd\n[00:04.00]next\n");
+        let line = &document.lines[0];
+        let next = Some(document.lines[1].time);
+        assert_eq!(line.sung_chars(ms(3000), next), 3);
+        // 没有结束时间时, 最后一个词开始后即算唱完
+        assert_eq!(line.sung_chars(ms(3000), None), 4);
+    }
+
+    #[test]
+    fn positive_offset_shows_lyrics_earlier() {
+        let document = parse_lrc("[offset:+500]\n[00:00.20]a\n[00:01.00]<00:01.00>b<00:02.00>\n");
+        assert_eq!(document.offset, 500);
+        // 提前到0之前的歌词从0开始
+        assert_eq!(document.lines[0].time, Duration::ZERO);
+        assert_eq!(document.lines[1].time, ms(500));
+        assert_eq!(document.lines[1].words[0].time, ms(500));
+        assert_eq!(document.lines[1].end, Some(ms(1500)));
+    }
+
+    #[test]
+    fn negative_offset_shows_lyrics_later() {
+        let document = parse_lrc("[00:01.00]a\n[offset:-250]\n");
+        assert_eq!(document.offset, -250);
+        assert_eq!(document.lines[0].time, ms(1250));
+    }
+
+    #[test]
+    fn id_tags() {
+        let document = parse_lrc(
+            "[ti: Title ]\n[AR:Artist]\n[al:Album]\n[length: 03:25.50]\n[by:]\n[xx:yy]\n",
+        );
+        assert_eq!(document.title.as_deref(), Some("Title"));
+        assert_eq!(document.artist.as_deref(), Some("Artist"));
+        assert_eq!(document.album.as_deref(), Some("Album"));
+        assert_eq!(document.length, Some(ms(205_500)));
+        assert!(document.lines.is_empty());
+        // 无法解析的值忽略
+        let document = parse_lrc("[length:abc]\n[offset:soon]\n[ar:]\n");
+        assert_eq!(document, LyricsDocument::default());
+    }
+
+    #[test]
+    fn length_without_fraction() {
+        assert_eq!(parse_length("4:05"), Some(ms(245_000)));
+        assert_eq!(parse_length("245"), None);
+    }
 }
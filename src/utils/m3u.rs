@@ -0,0 +1,117 @@
+use crate::{LoadedPlaylist, PlaylistItem, join_title, resolve_location, split_title};
+use std::{path::Path, time::Duration};
+
+/// 解析M3U/M3U8播放列表
+///
+/// `#EXTINF:时长,标题`作用于其后的第一项, 其余`#`开头的行忽略
+pub fn parse_m3u(text: &str, base: &Path) -> LoadedPlaylist {
+    let mut loaded = LoadedPlaylist::default();
+    let mut info: Option<(Option<Duration>, Option<String>, Option<String>)> = None;
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(extinf));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let (duration, artist, title) = info.take().unwrap_or_default();
+        match resolve_location(line, base) {
+            Some(path) => loaded.items.push(PlaylistItem {
+                path,
+                title,
+                artist,
+                duration,
+                ..Default::default()
+            }),
+            None => loaded.items.push(PlaylistItem {
+                title,
+                artist,
+                duration,
+                ..PlaylistItem::unresolved(line.to_string())
+            }),
+        }
+    }
+    loaded
+}
+
+/// 解析`#EXTINF:`之后的内容, 如`215 tvg-id="x",艺术家 - 标题`, 返回时长、艺术家与标题.
+/// 时长为负数表示未知
+fn parse_extinf(extinf: &str) -> (Option<Duration>, Option<String>, Option<String>) {
+    let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+    let duration = head
+        .split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .filter(|secs| *secs > 0.0)
+        .map(Duration::from_secs_f64);
+    let (artist, title) = split_title(title);
+    (duration, artist, title)
+}
+
+/// 生成M3U8播放列表
+pub fn write_m3u(items: &[PlaylistItem], base: &Path) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for item in items {
+        let title = join_title(item);
+        if title.is_some() || item.duration.is_some() {
+            let secs = item.duration.map_or(-1, |d| d.as_secs() as i64);
+            text.push_str(&format!("#EXTINF:{secs},{}\n", title.unwrap_or_default()));
+        }
+        text.push_str(&item.write_location(base));
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse_extinf_and_missing() {
+        let dir = TempDir::new("m3u-parse");
+        let base = dir.files(&["a.mp3", "b.mp3"]);
+        let text = "\u{feff}#EXTM3U\n#EXTINF:215 tvg-id=\"x\",Artist - Title\na.mp3\n\
+                    #EXTINF:-1,Gone\ngone.mp3\n# comment\nb.mp3\n";
+        let loaded = parse_m3u(text, &base);
+        let tracks: Vec<_> = loaded.tracks().collect();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].path, base.join("a.mp3"));
+        assert_eq!(tracks[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(tracks[0].title.as_deref(), Some("Title"));
+        assert_eq!(tracks[0].duration, Some(Duration::from_secs(215)));
+        assert_eq!(tracks[1].title, None);
+        assert_eq!(loaded.missing().collect::<Vec<_>>(), ["gone.mp3"]);
+        // 无法解析的项保留在原来的位置
+        assert_eq!(loaded.items[1].title.as_deref(), Some("Gone"));
+    }
+
+    #[test]
+    fn write_then_parse_round_trip() {
+        let dir = TempDir::new("m3u-round-trip");
+        let base = dir.files(&["a.mp3", "b c.mp3"]);
+        let items = vec![
+            PlaylistItem {
+                path: base.join("a.mp3"),
+                title: Some("Title".to_string()),
+                artist: Some("Artist".to_string()),
+                duration: Some(Duration::from_secs(90)),
+                ..Default::default()
+            },
+            PlaylistItem::unresolved("/gone/x.mp3".to_string()),
+            PlaylistItem {
+                path: base.join("b c.mp3"),
+                ..Default::default()
+            },
+        ];
+        let text = write_m3u(&items, &base);
+        assert!(text.contains("\na.mp3\n"), "{text}");
+        assert_eq!(parse_m3u(&text, &base).items, items);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::{LyricsDocument, PlaylistItem, ProbedFile};
+use regex::Regex;
+use std::{fs, path::Path, sync::LazyLock, time::Duration};
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+/// 默认的曲目信息显示格式
+pub const DEFAULT_TITLE_FORMAT: &str = "[{artist} - ]{title}";
+/// 文件名开头的曲号, 如`01. `、`1 - `、`01_`
+static NUMBER_REX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{1,3})\s*[.\-_)]\s*(.+)$").unwrap());
+
+/// 曲目元数据, 来自音频标签, 缺失时从文件名推断
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackMetadata {
+    /// 标题, 缺失时为文件名
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    /// 评分, 统一换算到`0.0..=1.0`
+    pub rating: Option<f32>,
+    pub duration: Option<Duration>,
+    /// 平均码率(kbps)
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    /// 编码格式简称, 如`flac`、`mp3`
+    pub codec: Option<String>,
+}
+
+impl TrackMetadata {
+    /// 从探测结果提取元数据, 无法探测时只从文件名推断
+    pub fn new(path: &Path, probed: Option<&ProbedFile>) -> Self {
+        let mut meta = Self::default();
+        if let Some(probed) = probed {
+            meta.read_tags(&probed.tags);
+            meta.read_params(path, probed);
+        }
+        meta.fill_from_file_name(path);
+        meta
+    }
+
+    /// 标签缺失时采用播放列表文件记录的标题、艺术家与时长
+    pub fn apply_hint(&mut self, path: &Path, hint: &PlaylistItem) {
+        self.apply_title(path, hint.title.as_ref(), hint.artist.as_ref());
+        self.duration = self.duration.or(hint.duration);
+    }
+
+    /// 标签缺失时采用歌词文件中的标题、艺术家、专辑与时长
+    pub fn apply_lyrics(&mut self, path: &Path, lyrics: &LyricsDocument) {
+        self.apply_title(path, lyrics.title.as_ref(), lyrics.artist.as_ref());
+        self.album = self.album.take().or(lyrics.album.clone());
+        self.duration = self.duration.or(lyrics.length);
+    }
+
+    /// 标题没有对应的标签时采用`title`, 艺术家只随标题一起采用, 且同样须没有对应的标签
+    fn apply_title(&mut self, path: &Path, title: Option<&String>, artist: Option<&String>) {
+        // 与仅从文件名推断的结果相同时, 视为没有对应的标签
+        let (_, guessed_artist, guessed_title) = parse_file_name(path);
+        let Some(title) = title.filter(|_| self.title == guessed_title) else {
+            return;
+        };
+        self.title = title.clone();
+        if artist.is_some() && self.artist == guessed_artist {
+            self.artist = artist.cloned();
+        }
+    }
+
+    /// 读取标准标签
+    fn read_tags(&mut self, tags: &[Tag]) {
+        for tag in tags {
+            // FMPS评分没有对应的标准标签
+            let fmps = tag.key.to_ascii_uppercase().ends_with("FMPS_RATING");
+            let Some(key) = tag.std_key.or(fmps.then_some(StandardTagKey::Rating)) else {
+                continue;
+            };
+            // 部分容器的字符串以`\0`结尾
+            let value = tag.value.to_string();
+            let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match key {
+                StandardTagKey::TrackTitle => self.title = value,
+                StandardTagKey::Artist => self.artist = Some(value),
+                StandardTagKey::Album => self.album = Some(value),
+                StandardTagKey::AlbumArtist => self.album_artist = Some(value),
+                StandardTagKey::TrackNumber => self.track_number = leading_number(&value),
+                StandardTagKey::DiscNumber => self.disc_number = leading_number(&value),
+                // 日期可能为`2001`或`2001-05-01`, 只取年份
+                StandardTagKey::Date | StandardTagKey::OriginalDate => {
+                    self.year = self.year.or(leading_number(&value));
+                }
+                StandardTagKey::Genre => self.genre = Some(value),
+                StandardTagKey::Rating => self.rating = parse_rating(&tag.key, &value),
+                _ => {}
+            }
+        }
+    }
+
+    /// 读取编码参数, 码率按文件大小与时长估算
+    fn read_params(&mut self, path: &Path, probed: &ProbedFile) {
+        let Some(params) = &probed.params else {
+            return;
+        };
+        self.sample_rate = params.sample_rate;
+        self.channels = params.channels.map(|c| c.count() as u16);
+        self.codec = symphonia::default::get_codecs()
+            .get_codec(params.codec)
+            .map(|codec| codec.short_name.to_string());
+        if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+            let time = time_base.calc_time(frames);
+            self.duration = Some(Duration::from_secs_f64(time.seconds as f64 + time.frac));
+        }
+        if let (Some(duration), Ok(file)) = (self.duration, fs::metadata(path))
+            && !duration.is_zero()
+        {
+            self.bitrate = Some((file.len() as f64 * 8.0 / duration.as_secs_f64() / 1000.0) as u32);
+        }
+    }
+
+    /// 按`[曲号.] [艺术家 - ]标题`的文件名补全缺失的字段
+    fn fill_from_file_name(&mut self, path: &Path) {
+        let (track, artist, title) = parse_file_name(path);
+        self.track_number = self.track_number.or(track);
+        if self.title.is_empty() {
+            self.title = title;
+        }
+        if self.artist.is_none() {
+            self.artist = artist;
+        }
+    }
+
+    /// 按格式字符串渲染
+    ///
+    /// `{title}`、`{artist}`、`{album}`、`{album_artist}`、`{track}`、`{disc}`、`{year}`、
+    /// `{genre}`、`{duration}`、`{bitrate}`、`{sample_rate}`、`{channels}`、`{codec}`替换为对应字段;
+    /// `[...]`内有任一字段缺失时整段省略
+    pub fn format(&self, template: &str) -> String {
+        let mut out = String::new();
+        // 可选段的内容及其中是否有缺失字段
+        let mut group: Option<(String, bool)> = None;
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            let (text, missing) = match c {
+                '[' if group.is_none() => {
+                    group = Some((String::new(), false));
+                    continue;
+                }
+                ']' if group.is_some() => {
+                    if let Some((text, false)) = group.take() {
+                        out.push_str(&text);
+                    }
+                    continue;
+                }
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    match self.field(&name) {
+                        Some(value) => (value, false),
+                        None => (String::new(), true),
+                    }
+                }
+                c => (c.to_string(), false),
+            };
+            match &mut group {
+                Some((group_text, group_missing)) => {
+                    group_text.push_str(&text);
+                    *group_missing |= missing;
+                }
+                None => out.push_str(&text),
+            }
+        }
+        // 未闭合的可选段按原样输出
+        if let Some((text, false)) = group {
+            out.push_str(&text);
+        }
+        out
+    }
+
+    /// 按名称取字段的显示值
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "title" => Some(self.title.clone()),
+            "artist" => self.artist.clone(),
+            "album" => self.album.clone(),
+            "album_artist" => self.album_artist.clone().or(self.artist.clone()),
+            "track" => self.track_number.map(|n| format!("{n:02}")),
+            "disc" => self.disc_number.map(|n| n.to_string()),
+            "year" => self.year.map(|n| n.to_string()),
+            "genre" => self.genre.clone(),
+            "duration" => self.duration.map(|d| {
+                let secs = d.as_secs();
+                format!("{:02}:{:02}", secs / 60, secs % 60)
+            }),
+            "bitrate" => self.bitrate.map(|n| format!("{n}kbps")),
+            "sample_rate" => self.sample_rate.map(|n| format!("{n}Hz")),
+            "channels" => self.channels.map(|n| n.to_string()),
+            "codec" => self.codec.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// 从`[曲号.] [艺术家 - ]标题`的文件名推断曲号、艺术家与标题
+fn parse_file_name(path: &Path) -> (Option<u32>, Option<String>, String) {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut rest = stem.as_str();
+    let mut track = None;
+    if let Some(caps) = NUMBER_REX.captures(&stem) {
+        track = caps[1].parse().ok();
+        rest = caps.get(2).map_or(rest, |m| m.as_str());
+    }
+    let (artist, title) = match rest.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), title),
+        None => (None, rest),
+    };
+    (track, artist, title.trim().to_string())
+}
+
+/// 解析开头的数字, 如`3/12`中的`3`
+fn leading_number(value: &str) -> Option<u32> {
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// 按标签类型确定评分的量程: ID3 POPM为0~255(0表示未评分), FMPS为0~1,
+/// 其余RATING标签为5星或百分制
+fn parse_rating(key: &str, value: &str) -> Option<f32> {
+    let rating: f32 = value.parse().ok()?;
+    let key = key.to_ascii_uppercase();
+    let scale = if key.starts_with("POPM") {
+        if rating == 0.0 {
+            return None;
+        }
+        255.0
+    } else if key.ends_with("FMPS_RATING") {
+        1.0
+    } else if rating <= 5.0 {
+        5.0
+    } else {
+        100.0
+    };
+    Some((rating / scale).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/music/01. Singer - Song.mp3";
+
+    fn lyrics(title: Option<&str>, artist: Option<&str>) -> LyricsDocument {
+        LyricsDocument {
+            title: title.map(str::to_string),
+            artist: artist.map(str::to_string),
+            album: Some("Album".to_string()),
+            length: Some(Duration::from_secs(200)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lyrics_fill_fields_guessed_from_file_name() {
+        let path = Path::new(PATH);
+        let mut meta = TrackMetadata::new(path, None);
+        meta.apply_lyrics(path, &lyrics(Some("Title"), Some("Artist")));
+        assert_eq!(meta.title, "Title");
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.album.as_deref(), Some("Album"));
+        assert_eq!(meta.duration, Some(Duration::from_secs(200)));
+    }
+
+    #[test]
+    fn lyrics_do_not_override_tags() {
+        let path = Path::new(PATH);
+        let mut meta = TrackMetadata {
+            title: "Tagged".to_string(),
+            album: Some("Tagged Album".to_string()),
+            duration: Some(Duration::from_secs(100)),
+            ..TrackMetadata::new(path, None)
+        };
+        meta.apply_lyrics(path, &lyrics(Some("Title"), Some("Artist")));
+        // 标题有标签时, 艺术家也不采用歌词中的
+        assert_eq!(meta.title, "Tagged");
+        assert_eq!(meta.artist.as_deref(), Some("Singer"));
+        assert_eq!(meta.album.as_deref(), Some("Tagged Album"));
+        assert_eq!(meta.duration, Some(Duration::from_secs(100)));
+        // 只有艺术家有标签时, 只采用标题
+        let mut meta = TrackMetadata {
+            artist: Some("Tagged Artist".to_string()),
+            ..TrackMetadata::new(path, None)
+        };
+        meta.apply_lyrics(path, &lyrics(Some("Title"), Some("Artist")));
+        assert_eq!(meta.title, "Title");
+        assert_eq!(meta.artist.as_deref(), Some("Tagged Artist"));
+    }
+
+    #[test]
+    fn artist_alone_is_not_applied() {
+        let path = Path::new(PATH);
+        let mut meta = TrackMetadata::new(path, None);
+        meta.apply_lyrics(path, &lyrics(None, Some("Artist")));
+        assert_eq!(meta.title, "Song");
+        assert_eq!(meta.artist.as_deref(), Some("Singer"));
+    }
+
+    #[test]
+    fn playlist_hint_follows_the_same_rule() {
+        let path = Path::new(PATH);
+        let hint = PlaylistItem {
+            title: Some("Title".to_string()),
+            artist: Some("Artist".to_string()),
+            duration: Some(Duration::from_secs(200)),
+            ..PlaylistItem::unresolved(PATH.to_string())
+        };
+        let mut meta = TrackMetadata::new(path, None);
+        meta.apply_hint(path, &hint);
+        assert_eq!(meta.title, "Title");
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.duration, Some(Duration::from_secs(200)));
+        let mut meta = TrackMetadata {
+            title: "Tagged".to_string(),
+            ..TrackMetadata::new(path, None)
+        };
+        meta.apply_hint(path, &hint);
+        assert_eq!(meta.title, "Tagged");
+        assert_eq!(meta.artist.as_deref(), Some("Singer"));
+    }
+
+    #[test]
+    fn file_name_gives_track_artist_and_title() {
+        let meta = TrackMetadata::new(Path::new(PATH), None);
+        assert_eq!(meta.track_number, Some(1));
+        assert_eq!(meta.artist.as_deref(), Some("Singer"));
+        assert_eq!(meta.title, "Song");
+        let meta = TrackMetadata::new(Path::new("/music/12_Song - Live.flac"), None);
+        assert_eq!(meta.track_number, Some(12));
+        assert_eq!(meta.artist.as_deref(), Some("Song"));
+        assert_eq!(meta.title, "Live");
+        // 不是曲号的数字保留在标题中
+        let meta = TrackMetadata::new(Path::new("/music/1984.mp3"), None);
+        assert_eq!((meta.track_number, meta.title.as_str()), (None, "1984"));
+    }
+
+    #[test]
+    fn file_name_only_fills_missing_fields() {
+        let mut meta = TrackMetadata {
+            title: "Tagged".to_string(),
+            track_number: Some(5),
+            ..Default::default()
+        };
+        meta.fill_from_file_name(Path::new(PATH));
+        assert_eq!(meta.title, "Tagged");
+        assert_eq!(meta.track_number, Some(5));
+        assert_eq!(meta.artist.as_deref(), Some("Singer"));
+    }
+
+    #[test]
+    fn format_drops_groups_with_missing_fields() {
+        let mut meta = TrackMetadata {
+            title: "Song".to_string(),
+            track_number: Some(3),
+            duration: Some(Duration::from_secs(125)),
+            ..Default::default()
+        };
+        assert_eq!(meta.format(DEFAULT_TITLE_FORMAT), "Song");
+        assert_eq!(
+            meta.format("[{track}. ]{title} ({duration})"),
+            "03. Song (02:05)"
+        );
+        assert_eq!(meta.format("{title}[ / {album} - {year}]"), "Song");
+        meta.artist = Some("Singer".to_string());
+        assert_eq!(meta.format(DEFAULT_TITLE_FORMAT), "Singer - Song");
+        // 专辑艺术家缺失时取艺术家, 不认识的字段为空
+        assert_eq!(meta.format("{album_artist}|{unknown}|"), "Singer||");
+        // 未闭合的可选段按原样输出
+        assert_eq!(meta.format("{title} [{artist}"), "Song Singer");
+    }
+
+    #[test]
+    fn rating_scales() {
+        assert_eq!(parse_rating("POPM:someone@example.com", "255"), Some(1.0));
+        assert_eq!(parse_rating("POPM", "0"), None);
+        assert_eq!(parse_rating("TXXX:FMPS_Rating", "0.6"), Some(0.6));
+        assert_eq!(parse_rating("RATING", "4"), Some(0.8));
+        assert_eq!(parse_rating("RATING", "80"), Some(0.8));
+        assert_eq!(parse_rating("RATING", "500"), Some(1.0));
+        assert_eq!(parse_rating("RATING", "five"), None);
+    }
+}
@@ -1,6 +1,32 @@
 mod audio;
+mod bookmarks;
 mod lyrics;
+mod metadata;
 mod controller;
+mod cue;
+mod library;
+mod m3u;
+mod pls;
+mod playlist_file;
+mod replaygain;
+mod session;
+mod sleep;
+mod tags;
+mod watcher;
+mod xspf;
 pub use controller::*;
+pub use cue::*;
 pub use lyrics::*;
-pub use audio::*;
\ No newline at end of file
+pub use metadata::*;
+pub use audio::*;
+pub use bookmarks::*;
+pub use library::*;
+pub use m3u::*;
+pub use pls::*;
+pub use playlist_file::*;
+pub use replaygain::*;
+pub use session::*;
+pub use sleep::*;
+pub use tags::*;
+pub use watcher::*;
+pub use xspf::*;
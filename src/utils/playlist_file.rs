@@ -0,0 +1,318 @@
+use crate::{
+    AnyResult, anyhow, parse_m3u, parse_pls, parse_xspf, write_m3u, write_pls, write_xspf,
+};
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+/// 播放列表文件中的一项
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistItem {
+    /// 曲目的绝对路径, 无法解析的项为文件中的原文
+    pub path: PathBuf,
+    /// 不存在或无法解析的项在文件中的原文, 保存时原样写回
+    pub location: Option<String>,
+    /// 播放列表记录的标题
+    pub title: Option<String>,
+    /// 播放列表记录的艺术家
+    pub artist: Option<String>,
+    /// 播放列表记录的时长
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistItem {
+    /// 不存在或无法解析的项
+    pub fn unresolved(location: String) -> Self {
+        Self {
+            path: PathBuf::from(&location),
+            location: Some(location),
+            ..Default::default()
+        }
+    }
+    /// 写入播放列表时的位置, 无法解析的项为原文
+    pub fn write_location(&self, base: &Path) -> String {
+        match &self.location {
+            Some(location) => location.clone(),
+            None => relative_location(&self.path, base),
+        }
+    }
+}
+
+/// 读取播放列表文件的结果
+#[derive(Clone, Debug, Default)]
+pub struct LoadedPlaylist {
+    /// 文件中的各项, 按文件中的顺序. 不存在或无法解析的项也记录在内, 以便保存时写回
+    pub items: Vec<PlaylistItem>,
+}
+
+impl LoadedPlaylist {
+    /// 存在的曲目
+    pub fn tracks(&self) -> impl Iterator<Item = &PlaylistItem> {
+        self.items.iter().filter(|item| item.location.is_none())
+    }
+    /// 不存在或无法解析的项在文件中的原文
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .filter_map(|item| item.location.as_deref())
+    }
+}
+
+/// 播放列表文件格式, 按扩展名区分
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// 读取播放列表文件, 相对路径相对于文件所在目录
+pub fn load_playlist(path: &Path) -> AnyResult<LoadedPlaylist> {
+    let format = Format::of(path).ok_or(anyhow!("不支持的播放列表格式: {}", path.display()))?;
+    // 旧的`.m3u`、`.pls`可能不是UTF-8编码, 无法解码的字符不影响其余内容
+    let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+    let base = base_dir(path);
+    match format {
+        Format::M3u => Ok(parse_m3u(&text, &base)),
+        Format::Pls => Ok(parse_pls(&text, &base)),
+        Format::Xspf => parse_xspf(&text, &base),
+    }
+}
+
+/// 保存播放列表文件, 按扩展名选择格式, 无法识别时保存为M3U8.
+/// 位于文件所在目录下的曲目记录为相对路径
+pub fn save_playlist(path: &Path, items: &[PlaylistItem]) -> AnyResult<()> {
+    let base = base_dir(path);
+    let text = match Format::of(path).unwrap_or(Format::M3u) {
+        Format::M3u => write_m3u(items, &base),
+        Format::Pls => write_pls(items, &base),
+        Format::Xspf => write_xspf(items, &base),
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// 播放列表文件所在目录的绝对路径
+fn base_dir(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => env::current_dir().unwrap_or_default(),
+    };
+    dir.canonicalize().unwrap_or(dir)
+}
+
+/// 把播放列表中的位置解析为存在的文件的绝对路径
+///
+/// 支持绝对路径、相对于`base`的路径、`file://`地址与百分号编码的路径, 不支持网络地址
+pub fn resolve_location(location: &str, base: &Path) -> Option<PathBuf> {
+    let location = location.trim();
+    if let Some(rest) = location.strip_prefix("file://") {
+        // `file:///music/a.mp3`的主机部分为空; Windows地址形如`file:///C:/music/a.mp3`
+        let path = percent_decode(rest.strip_prefix("localhost").unwrap_or(rest));
+        let path = match path.as_bytes() {
+            [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+            _ => &path,
+        };
+        return existing(base, path);
+    }
+    if location.contains("://") {
+        return None;
+    }
+    existing(base, location)
+        .or_else(|| existing(base, &percent_decode(location)))
+        // Windows下导出的列表使用反斜杠分隔
+        .or_else(|| existing(base, &location.replace('\\', "/")))
+}
+
+/// 路径存在时返回其绝对路径
+fn existing(base: &Path, path: &str) -> Option<PathBuf> {
+    let path = base.join(path);
+    path.is_file().then(|| path.canonicalize().unwrap_or(path))
+}
+
+/// 写入播放列表时的位置: 相对于`base`的路径, 必要时以`..`回到上级目录.
+/// 除根目录或盘符外没有共同的上级目录时为绝对路径
+pub fn relative_location(path: &Path, base: &Path) -> String {
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let shares_dir = path
+        .components()
+        .take(common)
+        .any(|c| matches!(c, Component::Normal(_)));
+    if !shares_dir {
+        return path.to_string_lossy().into_owned();
+    }
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    relative.to_string_lossy().into_owned()
+}
+
+/// 按URI的要求编码路径, 保留`/`与不需要编码的字符
+pub fn percent_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+/// 拆分`艺术家 - 标题`形式的标题
+pub fn split_title(title: &str) -> (Option<String>, Option<String>) {
+    let title = title.trim();
+    let (artist, title) = match title.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim()), title.trim()),
+        None => (None, title),
+    };
+    let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+    (artist.and_then(non_empty), non_empty(title))
+}
+
+/// 合并为`艺术家 - 标题`形式的标题
+pub fn join_title(item: &PlaylistItem) -> Option<String> {
+    match (&item.artist, &item.title) {
+        (Some(artist), Some(title)) => Some(format!("{artist} - {title}")),
+        (_, title) => title.clone(),
+    }
+}
+
+/// 解码`%20`形式的百分号编码, 非法的编码原样保留
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn percent_decode_round_trip() {
+        assert_eq!(percent_decode("a%20b%E4%B8%AD"), "a b中");
+        // 非法的编码原样保留
+        assert_eq!(percent_decode("100%zz%4"), "100%zz%4");
+        let text = "/音乐/Artist - Title (Live) #1.mp3";
+        let encoded = percent_encode(text);
+        assert!(encoded.starts_with("/%E9%9F%B3"), "{encoded}");
+        assert!(!encoded.contains(' '));
+        assert_eq!(percent_decode(&encoded), text);
+    }
+
+    #[test]
+    fn split_and_join_title() {
+        assert_eq!(
+            split_title(" Artist - Title "),
+            (Some("Artist".to_string()), Some("Title".to_string()))
+        );
+        assert_eq!(split_title("Title"), (None, Some("Title".to_string())));
+        assert_eq!(split_title(""), (None, None));
+        let (artist, title) = split_title("A - B - C");
+        let item = PlaylistItem {
+            artist,
+            title,
+            ..Default::default()
+        };
+        assert_eq!(join_title(&item).as_deref(), Some("A - B - C"));
+    }
+
+    #[test]
+    fn relative_location_climbs_to_common_dir() {
+        let base = Path::new("/music/lists");
+        assert_eq!(
+            relative_location(Path::new("/music/lists/a.mp3"), base),
+            "a.mp3"
+        );
+        assert_eq!(
+            relative_location(Path::new("/music/x/b.mp3"), base),
+            "../x/b.mp3"
+        );
+        // 只有根目录相同时为绝对路径
+        assert_eq!(
+            relative_location(Path::new("/other/c.mp3"), base),
+            "/other/c.mp3"
+        );
+    }
+
+    #[test]
+    fn resolve_location_forms() {
+        let dir = TempDir::new("resolve");
+        let base = dir.files(&["a b.mp3", "sub/c.mp3"]);
+        let file = base.join("a b.mp3");
+        assert_eq!(resolve_location("a b.mp3", &base), Some(file.clone()));
+        assert_eq!(resolve_location("a%20b.mp3", &base), Some(file.clone()));
+        assert_eq!(
+            resolve_location(&file.to_string_lossy(), &base),
+            Some(file.clone())
+        );
+        let uri = percent_encode(&file.to_string_lossy());
+        assert_eq!(
+            resolve_location(&format!("file://{uri}"), &base),
+            Some(file.clone())
+        );
+        assert_eq!(
+            resolve_location(&format!("file://localhost{uri}"), &base),
+            Some(file)
+        );
+        assert_eq!(
+            resolve_location("sub\\c.mp3", &base),
+            Some(base.join("sub/c.mp3"))
+        );
+        assert_eq!(resolve_location("missing.mp3", &base), None);
+        assert_eq!(resolve_location("http://example.com/a.mp3", &base), None);
+    }
+
+    #[test]
+    fn relative_location_resolves_back() {
+        let dir = TempDir::new("relative");
+        let root = dir.files(&["music/a.mp3", "lists/x.m3u"]);
+        let base = root.join("lists");
+        let path = root.join("music/a.mp3");
+        let location = relative_location(&path, &base);
+        assert_eq!(location, "../music/a.mp3");
+        assert_eq!(resolve_location(&location, &base), Some(path));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{LoadedPlaylist, PlaylistItem, join_title, resolve_location, split_title};
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+/// PLS中编号相同的一组键
+#[derive(Default)]
+struct PlsEntry {
+    location: Option<String>,
+    title: Option<String>,
+    duration: Option<Duration>,
+}
+
+/// 解析PLS播放列表
+///
+/// `FileN`、`TitleN`、`LengthN`按编号`N`对应同一项, 按编号顺序排列, 键名不区分大小写
+pub fn parse_pls(text: &str, base: &Path) -> LoadedPlaylist {
+    let mut entries: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let Ok(number) = key[split..].parse::<u32>() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match &key[..split] {
+            "file" => entry.location = Some(value.to_string()),
+            "title" => entry.title = Some(value.to_string()),
+            // 时长为`-1`表示未知
+            "length" => {
+                entry.duration = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| *secs > 0.0)
+                    .map(Duration::from_secs_f64)
+            }
+            _ => {}
+        }
+    }
+    let mut loaded = LoadedPlaylist::default();
+    for entry in entries.into_values() {
+        let Some(location) = entry.location else {
+            continue;
+        };
+        let (artist, title) = entry.title.as_deref().map(split_title).unwrap_or_default();
+        let item = match resolve_location(&location, base) {
+            Some(path) => PlaylistItem {
+                path,
+                ..Default::default()
+            },
+            None => PlaylistItem::unresolved(location),
+        };
+        loaded.items.push(PlaylistItem {
+            title,
+            artist,
+            duration: entry.duration,
+            ..item
+        });
+    }
+    loaded
+}
+
+/// 生成PLS播放列表
+pub fn write_pls(items: &[PlaylistItem], base: &Path) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, item) in items.iter().enumerate() {
+        let n = i + 1;
+        text.push_str(&format!("File{n}={}\n", item.write_location(base)));
+        if let Some(title) = join_title(item) {
+            text.push_str(&format!("Title{n}={title}\n"));
+        }
+        let secs = item.duration.map_or(-1, |d| d.as_secs() as i64);
+        text.push_str(&format!("Length{n}={secs}\n"));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", items.len()));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse_numbered_keys() {
+        let dir = TempDir::new("pls-parse");
+        let base = dir.files(&["a.mp3", "b.mp3"]);
+        let text = "[playlist]\nFILE2=b.mp3\nTitle1=Artist - Title\nFile1=a.mp3\nLength1=61\n\
+                    Length2=-1\nFile3=gone.mp3\nNumberOfEntries=3\nVersion=2\n";
+        let loaded = parse_pls(text, &base);
+        let tracks: Vec<_> = loaded.tracks().collect();
+        // 按编号排列, 键名不区分大小写
+        assert_eq!(tracks[0].path, base.join("a.mp3"));
+        assert_eq!(tracks[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(tracks[0].title.as_deref(), Some("Title"));
+        assert_eq!(tracks[0].duration, Some(Duration::from_secs(61)));
+        assert_eq!(tracks[1].path, base.join("b.mp3"));
+        assert_eq!(tracks[1].duration, None);
+        assert_eq!(loaded.missing().collect::<Vec<_>>(), ["gone.mp3"]);
+    }
+
+    #[test]
+    fn write_then_parse_round_trip() {
+        let dir = TempDir::new("pls-round-trip");
+        let base = dir.files(&["a.mp3", "b.mp3"]);
+        let items = vec![
+            PlaylistItem {
+                path: base.join("a.mp3"),
+                title: Some("Title".to_string()),
+                artist: Some("Artist".to_string()),
+                duration: Some(Duration::from_secs(90)),
+                ..Default::default()
+            },
+            PlaylistItem {
+                title: Some("Gone".to_string()),
+                ..PlaylistItem::unresolved("http://example.com/x.mp3".to_string())
+            },
+            PlaylistItem {
+                path: base.join("b.mp3"),
+                ..Default::default()
+            },
+        ];
+        let text = write_pls(&items, &base);
+        assert!(text.contains("File1=a.mp3\n"), "{text}");
+        assert!(text.contains("NumberOfEntries=3\n"), "{text}");
+        assert_eq!(parse_pls(&text, &base).items, items);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{AnyResult, GainControl, LoudnessMeter};
+use clap::ValueEnum;
+use rodio::{Decoder, Source};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+/// ReplayGain 2.0 的参考响度(LUFS)
+const REFERENCE_LUFS: f64 = -18.0;
+/// 最多缓存的测量结果数, 超出时清除已完成的结果
+const MAX_MEASURED: usize = 1024;
+
+/// 响度归一化模式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    /// 不做归一化
+    #[default]
+    Off,
+    /// 按曲目增益
+    Track,
+    /// 按专辑增益, 缺失时退回曲目增益
+    Album,
+}
+
+/// 自行测量得到的增益与峰值
+#[derive(Clone, Copy)]
+struct Measured {
+    gain_db: f32,
+    peak: f32,
+}
+
+/// 响度测量状态
+enum Measure {
+    /// 测量中, 记录等待结果的增益控制
+    Pending(Vec<GainControl>),
+    /// 测量完成, 无法测量时为`None`
+    Done(Option<Measured>),
+}
+
+/// 响度归一化设置
+#[derive(Clone, Default)]
+pub struct ReplayGain {
+    pub mode: ReplayGainMode,
+    /// 前置放大(dB)
+    pub preamp: f32,
+    /// 按峰值限制增益, 防止削波
+    pub prevent_clipping: bool,
+    /// 缺少增益标签的音频的测量结果
+    measured: Arc<Mutex<HashMap<PathBuf, Measure>>>,
+}
+
+impl ReplayGain {
+    pub fn new(mode: ReplayGainMode, preamp: f32, prevent_clipping: bool) -> Self {
+        Self {
+            mode,
+            preamp,
+            prevent_clipping,
+            measured: Default::default(),
+        }
+    }
+
+    /// 计算音频的增益控制
+    ///
+    /// 优先使用`REPLAYGAIN_*`标签, 标签缺失时在后台测量EBU R128响度, 完成后再更新增益
+    pub fn gain_for(&self, path: &Path, tags: &[Tag]) -> GainControl {
+        if self.mode == ReplayGainMode::Off {
+            return GainControl::new(1.0);
+        }
+        if let Some((gain_db, peak)) = tag_gain(tags, self.mode) {
+            return GainControl::new(self.linear(gain_db, peak));
+        }
+        let control = GainControl::new(1.0);
+        let mut measured = self.measured.lock().unwrap();
+        match measured.get_mut(path) {
+            Some(Measure::Done(Some(m))) => control.set(self.linear(m.gain_db, Some(m.peak))),
+            Some(Measure::Done(None)) => {}
+            Some(Measure::Pending(waiting)) => waiting.push(control.clone()),
+            None => {
+                if measured.len() >= MAX_MEASURED {
+                    measured.retain(|_, m| matches!(m, Measure::Pending(_)));
+                }
+                measured.insert(path.to_path_buf(), Measure::Pending(vec![control.clone()]));
+                self.spawn_measure(path.to_path_buf());
+            }
+        }
+        control
+    }
+
+    /// 增益(dB)换算为线性倍数, 并加上前置放大与削波保护
+    fn linear(&self, gain_db: f32, peak: Option<f32>) -> f32 {
+        let mut linear = 10f32.powf((gain_db + self.preamp) / 20.0);
+        if self.prevent_clipping
+            && let Some(peak) = peak
+            && peak > 0.0
+        {
+            linear = linear.min(1.0 / peak);
+        }
+        linear
+    }
+
+    /// 派生子线程测量响度, 完成后更新所有等待中的增益控制
+    fn spawn_measure(&self, path: PathBuf) {
+        let this = self.clone();
+        thread::spawn(move || {
+            let result = measure(&path).ok().flatten();
+            let mut measured = this.measured.lock().unwrap();
+            if let Some(Measure::Pending(waiting)) = measured.insert(path, Measure::Done(result))
+                && let Some(m) = result
+            {
+                for control in waiting {
+                    control.set(this.linear(m.gain_db, Some(m.peak)));
+                }
+            }
+        });
+    }
+}
+
+/// 从标签读取增益(dB)与峰值, 专辑模式下缺少专辑增益时退回曲目增益
+fn tag_gain(tags: &[Tag], mode: ReplayGainMode) -> Option<(f32, Option<f32>)> {
+    use StandardTagKey::*;
+    let album = (ReplayGainAlbumGain, ReplayGainAlbumPeak);
+    let track = (ReplayGainTrackGain, ReplayGainTrackPeak);
+    let order = match mode {
+        ReplayGainMode::Album => vec![album, track],
+        _ => vec![track],
+    };
+    let std_value = |key| {
+        tags.iter()
+            .find(|tag| tag.std_key == Some(key))
+            .and_then(|tag| parse_number(&tag.value.to_string()))
+    };
+    order
+        .into_iter()
+        .find_map(|(gain_key, peak_key)| Some((std_value(gain_key)?, std_value(peak_key))))
+}
+
+/// 解析形如`-6.52 dB`的数值
+fn parse_number(value: &str) -> Option<f32> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+/// 完整解码音频, 测量EBU R128综合响度与采样峰值
+fn measure(path: &Path) -> AnyResult<Option<Measured>> {
+    let mut source = Decoder::try_from(File::open(path)?)?;
+    let mut meter = LoudnessMeter::new(source.channels(), source.sample_rate());
+    for sample in source.by_ref() {
+        meter.push(sample);
+    }
+    Ok(meter.integrated().map(|lufs| Measured {
+        gain_db: (REFERENCE_LUFS - lufs) as f32,
+        peak: meter.peak(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::meta::Value;
+
+    fn tag(key: StandardTagKey, value: &str) -> Tag {
+        Tag::new(Some(key), &format!("{key:?}"), Value::from(value))
+    }
+
+    #[test]
+    fn number_with_unit() {
+        assert_eq!(parse_number("-6.52 dB"), Some(-6.52));
+        assert_eq!(parse_number(" 0.988553"), Some(0.988553));
+        assert_eq!(parse_number("+3dB"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn album_gain_falls_back_to_track() {
+        use StandardTagKey::*;
+        let tags = [
+            tag(ReplayGainTrackGain, "-6.5 dB"),
+            tag(ReplayGainTrackPeak, "0.9"),
+            tag(ReplayGainAlbumGain, "-8 dB"),
+        ];
+        assert_eq!(
+            tag_gain(&tags, ReplayGainMode::Track),
+            Some((-6.5, Some(0.9)))
+        );
+        assert_eq!(tag_gain(&tags, ReplayGainMode::Album), Some((-8.0, None)));
+        assert_eq!(
+            tag_gain(&tags[..2], ReplayGainMode::Album),
+            Some((-6.5, Some(0.9)))
+        );
+        assert_eq!(tag_gain(&[], ReplayGainMode::Track), None);
+    }
+
+    #[test]
+    fn clipping_prevention_limits_gain() {
+        let gain = ReplayGain::new(ReplayGainMode::Track, 0.0, true);
+        assert!((gain.linear(-20.0, Some(0.5)) - 0.1).abs() < 1e-6);
+        assert!((gain.linear(20.0, Some(0.5)) - 2.0).abs() < 1e-6);
+        let gain = ReplayGain::new(ReplayGainMode::Track, 6.0, false);
+        assert!((gain.linear(14.0, Some(0.5)) - 10.0).abs() < 1e-5);
+    }
+}
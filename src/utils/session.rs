@@ -0,0 +1,125 @@
+use crate::{AnyResult, RepeatMode, ShuffleMode, anyhow};
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use ini::Ini;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// 会话文件名
+const SESSION_FILE: &str = "session.ini";
+
+/// 列表中的一首曲目: 文件路径, CUE表曲目另记起点
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackKey {
+    pub path: PathBuf,
+    /// CUE表曲目在文件中的起点(毫秒), 整个文件为一首时为`None`
+    pub cue_start: Option<u64>,
+}
+
+/// 上次退出时的播放状态, 下次启动时从这里继续
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// 音乐目录, 与`playlist`只有一个
+    pub dir: Option<PathBuf>,
+    /// 播放列表文件
+    pub playlist: Option<PathBuf>,
+    /// 当前曲目, 列表已播完时为`None`
+    pub track: Option<TrackKey>,
+    /// 当前曲目的播放位置
+    pub position: Duration,
+    /// 待播队列
+    pub queue: Vec<TrackKey>,
+    pub shuffle: ShuffleMode,
+    pub repeat: RepeatMode,
+}
+
+impl Session {
+    /// 会话文件路径, 与音乐库索引同在数据目录
+    fn file_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "mini-music").map(|dirs| dirs.data_dir().join(SESSION_FILE))
+    }
+
+    /// 读取上次的会话, 不存在或无法读取时为`None`
+    pub fn load() -> Option<Self> {
+        let conf = Ini::load_from_file(Session::file_path()?).ok()?;
+        let get = |key: &str| conf.get_from(Some("Session"), key);
+        let track_key = |path: &str, cue: Option<&str>| TrackKey {
+            path: PathBuf::from(path),
+            cue_start: cue.and_then(|v| v.parse().ok()),
+        };
+        let queue = conf
+            .section(Some("Queue"))
+            .map_or_else(Vec::new, |section| {
+                (0..)
+                    .map_while(|i| section.get(i.to_string()).map(|path| (i, path)))
+                    .map(|(i, path)| track_key(path, section.get(format!("{i}.cue"))))
+                    .collect()
+            });
+        Some(Self {
+            dir: get("dir").map(PathBuf::from),
+            playlist: get("playlist").map(PathBuf::from),
+            track: get("track").map(|path| track_key(path, get("cue_start"))),
+            position: Duration::from_millis(get("position").and_then(|v| v.parse().ok())?),
+            queue,
+            shuffle: get("shuffle")
+                .and_then(|v| ShuffleMode::from_str(v, true).ok())
+                .unwrap_or_default(),
+            repeat: get("repeat")
+                .and_then(|v| RepeatMode::from_str(v, true).ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// 写入会话文件
+    pub fn save(&self) -> AnyResult<()> {
+        let path = Session::file_path().ok_or(anyhow!("找不到数据目录"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut conf = Ini::new();
+        let mut section = conf.with_section(Some("Session"));
+        section
+            .set("position", self.position.as_millis().to_string())
+            .set(
+                "shuffle",
+                self.shuffle.to_possible_value().unwrap().get_name(),
+            )
+            .set(
+                "repeat",
+                self.repeat.to_possible_value().unwrap().get_name(),
+            );
+        if let Some(dir) = &self.dir {
+            section.set("dir", dir.to_string_lossy());
+        }
+        if let Some(playlist) = &self.playlist {
+            section.set("playlist", playlist.to_string_lossy());
+        }
+        if let Some(track) = &self.track {
+            section.set("track", track.path.to_string_lossy());
+            if let Some(start) = track.cue_start {
+                section.set("cue_start", start.to_string());
+            }
+        }
+        let mut section = conf.with_section(Some("Queue"));
+        for (i, track) in self.queue.iter().enumerate() {
+            section.set(i.to_string(), track.path.to_string_lossy());
+            if let Some(start) = track.cue_start {
+                section.set(format!("{i}.cue"), start.to_string());
+            }
+        }
+        // 先写临时文件再替换, 避免中断时损坏会话
+        let tmp = path.with_extension("tmp");
+        conf.write_to_file(&tmp)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// 会话是否来自同一个音乐目录或播放列表文件
+    pub fn same_source(&self, dir: Option<&Path>, playlist: Option<&Path>) -> bool {
+        let canonical = |path: Option<&Path>| path.map(|p| p.canonicalize().unwrap_or(p.into()));
+        canonical(dir) == self.dir && canonical(playlist) == self.playlist
+    }
+}
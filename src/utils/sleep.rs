@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+/// 定时停止前的淡出时长
+pub const SLEEP_FADE: Duration = Duration::from_secs(30);
+
+/// 定时停止的方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SleepMode {
+    /// 指定时长后停止
+    After(Duration),
+    /// 当前曲目之后再播放指定数量的曲目后停止, 0表示播完当前曲目
+    Tracks(u32),
+}
+
+impl SleepMode {
+    /// 按键依次切换的定时方式
+    const CYCLE: [SleepMode; 5] = [
+        SleepMode::After(Duration::from_secs(15 * 60)),
+        SleepMode::After(Duration::from_secs(30 * 60)),
+        SleepMode::After(Duration::from_secs(60 * 60)),
+        SleepMode::After(Duration::from_secs(90 * 60)),
+        SleepMode::Tracks(0),
+    ];
+
+    /// 解析`30`/`30m`(分钟)、`2h`(小时)、`current`(播完当前曲目)、`3t`(再播3首)
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim().to_ascii_lowercase();
+        let number = |s: &str| s.parse::<u64>().map_err(|_| format!("无效的定时: {spec}"));
+        if spec == "current" {
+            return Ok(SleepMode::Tracks(0));
+        }
+        if let Some(tracks) = spec.strip_suffix('t') {
+            let tracks = u32::try_from(number(tracks)?).map_err(|_| format!("曲目数过大: {spec}"));
+            return tracks.map(SleepMode::Tracks);
+        }
+        let minutes = match spec.strip_suffix('h') {
+            Some(hours) => number(hours)?.checked_mul(60),
+            None => Some(number(spec.strip_suffix('m').unwrap_or(&spec))?),
+        };
+        match minutes.and_then(|minutes| minutes.checked_mul(60)) {
+            Some(0) => Err("定时时长须大于0".to_string()),
+            Some(secs) => Ok(SleepMode::After(Duration::from_secs(secs))),
+            None => Err(format!("定时时长过长: {spec}")),
+        }
+    }
+
+    /// 切换到下一个定时方式, 最后一个之后关闭定时
+    pub fn cycle(current: Option<SleepMode>) -> Option<SleepMode> {
+        match current {
+            None => Some(Self::CYCLE[0]),
+            Some(mode) => Self::CYCLE
+                .iter()
+                .position(|m| *m == mode)
+                .and_then(|idx| Self::CYCLE.get(idx + 1))
+                .copied(),
+        }
+    }
+}
+
+/// 定时停止
+#[derive(Clone, Copy, Debug)]
+pub struct SleepTimer {
+    pub mode: SleepMode,
+    /// 开始计时的时刻
+    started: Instant,
+}
+
+impl SleepTimer {
+    pub fn new(mode: SleepMode) -> Self {
+        Self {
+            mode,
+            started: Instant::now(),
+        }
+    }
+    /// 按时长定时的剩余时间
+    pub fn remaining(&self) -> Option<Duration> {
+        match self.mode {
+            SleepMode::After(duration) => Some(duration.saturating_sub(self.started.elapsed())),
+            SleepMode::Tracks(_) => None,
+        }
+    }
+    /// 当前曲目是否为停止前的最后一首
+    pub fn is_last_track(&self) -> bool {
+        self.mode == SleepMode::Tracks(0)
+    }
+    /// 一首曲目播放完毕, 计数减一
+    pub fn track_finished(&mut self) {
+        if let SleepMode::Tracks(n) = &mut self.mode {
+            *n = n.saturating_sub(1);
+        }
+    }
+}
+
+/// 距停止还有`remaining`时的音量系数, 最后`SLEEP_FADE`内线性减小到0
+pub fn sleep_fade(remaining: Duration) -> f32 {
+    (remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rejects_out_of_range() {
+        assert_eq!(SleepMode::parse("3t"), Ok(SleepMode::Tracks(3)));
+        assert_eq!(SleepMode::parse("2h"), Ok(SleepMode::After(Duration::from_secs(7200))));
+        assert!(SleepMode::parse("4294967297t").is_err());
+        assert!(SleepMode::parse("18446744073709551615h").is_err());
+        assert!(SleepMode::parse("0").is_err());
+    }
+}
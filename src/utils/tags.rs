@@ -0,0 +1,97 @@
+use crate::{AnyResult, cue_text};
+use std::{fs::File, path::Path, time::Duration};
+use symphonia::core::{
+    codecs::{CODEC_TYPE_NULL, CodecParameters},
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataRevision, Tag},
+    probe::Hint,
+};
+
+/// 探测音频文件得到的标签与编码参数
+pub struct ProbedFile {
+    /// 全部标签
+    pub tags: Vec<Tag>,
+    /// 第一条音轨的编码参数
+    pub params: Option<CodecParameters>,
+    /// 嵌入的CUE表文本, 来自`CUESHEET`标签或FLAC的CUESHEET元数据块
+    pub cue_sheet: Option<String>,
+}
+
+/// 读取音频文件元数据中的全部标签
+pub fn read_tags(path: &Path) -> AnyResult<Vec<Tag>> {
+    Ok(probe_file(path)?.tags)
+}
+
+/// 探测音频文件, 读取标签和编码参数
+///
+/// 标签包括探测阶段在容器外读到的标签(如MP3的ID3v2)和容器内的标签
+pub fn probe_file(path: &Path) -> AnyResult<ProbedFile> {
+    // 1. 创建媒体源流
+    let src = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    // 2. 探测格式
+    // 创建一个 Hint 来帮助探测。如果文件有扩展名，这会很有用。
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|s| s.to_str()) {
+        hint.with_extension(extension);
+    }
+    // 默认的探测选项和元数据选项
+    let format_opts: FormatOptions = Default::default();
+    let metadata_opts = Default::default();
+    // 探测媒体源的格式
+    let mut probed =
+        symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
+
+    // 3. 收集两处元数据的最新版本中的标签
+    let mut tags = Vec::new();
+    let collect = |tags: &mut Vec<Tag>, rev: Option<&MetadataRevision>| {
+        if let Some(rev) = rev {
+            tags.extend(rev.tags().iter().cloned());
+        }
+    };
+    if let Some(metadata) = probed.metadata.get() {
+        collect(&mut tags, metadata.current());
+    }
+    collect(&mut tags, probed.format.metadata().current());
+    // 4. 第一条可解码音轨的编码参数
+    let params = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .map(|track| track.codec_params.clone());
+    // 5. 嵌入的CUE表, 元数据块只有曲目起点, 转为CUE表文本
+    let cue_sheet = find_tag(&tags, "CUESHEET")
+        .map(|tag| tag.value.to_string())
+        .or_else(|| {
+            let sample_rate = params.as_ref()?.sample_rate?;
+            // 曲号170、255为导出区, 不是曲目
+            let starts: Vec<(u32, Duration)> = probed
+                .format
+                .cues()
+                .iter()
+                .filter(|cue| cue.index < 170)
+                .map(|cue| {
+                    // 有INDEX 00时第二个索引点才是INDEX 01
+                    let offset = match cue.points.as_slice() {
+                        [_, index, ..] | [index] => index.start_offset_ts,
+                        [] => 0,
+                    };
+                    let samples = cue.start_ts + offset;
+                    (cue.index, Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+                })
+                .collect();
+            (!starts.is_empty()).then(|| cue_text(&starts))
+        });
+    Ok(ProbedFile {
+        tags,
+        params,
+        cue_sheet,
+    })
+}
+
+/// 按标签名(不区分大小写)查找标签值
+pub fn find_tag<'a>(tags: &'a [Tag], key: &str) -> Option<&'a Tag> {
+    tags.iter().find(|tag| tag.key.eq_ignore_ascii_case(key))
+}
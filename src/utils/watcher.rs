@@ -0,0 +1,74 @@
+use crate::{AnyResult, is_audio};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{AccessKind, AccessMode, ModifyKind},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+use walkdir::WalkDir;
+
+/// 音乐目录中的文件变化
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FsChange {
+    /// 新增的音频文件
+    Added(PathBuf),
+    /// 被删除或移走的文件或目录, 其下的音频都应移出列表
+    Removed(PathBuf),
+}
+
+/// 监视音乐目录, 收集文件的增删与移动
+pub struct DirWatcher {
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+}
+
+impl DirWatcher {
+    /// 递归监视目录
+    pub fn new(dir: &Path) -> AnyResult<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// 取出目前为止的全部变化, 不阻塞
+    ///
+    /// 按路径当前是否存在判断新增还是删除, 重命名与跨目录移动也由此得到处理
+    pub fn changes(&self) -> Vec<FsChange> {
+        let mut changes = Vec::new();
+        for event in self.rx.try_iter().filter_map(|e| e.ok()) {
+            // 只关心增删、重命名和写入完成, 写入过程中的修改不必逐个处理
+            match event.kind {
+                EventKind::Create(_)
+                | EventKind::Remove(_)
+                | EventKind::Modify(ModifyKind::Name(_))
+                | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {}
+                _ => continue,
+            }
+            for path in event.paths {
+                if path.is_dir() {
+                    // 移入的目录不会为其中的文件单独产生事件
+                    changes.extend(
+                        WalkDir::new(&path)
+                            .into_iter()
+                            .filter_map(|e| e.ok())
+                            .filter(|e| e.file_type().is_file() && is_audio(e.path()))
+                            .map(|e| FsChange::Added(e.into_path())),
+                    );
+                } else if path.is_file() {
+                    if is_audio(&path) {
+                        changes.push(FsChange::Added(path));
+                    }
+                } else {
+                    changes.push(FsChange::Removed(path));
+                }
+            }
+        }
+        changes
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    AnyResult, LoadedPlaylist, PlaylistItem, percent_encode, relative_location, resolve_location,
+};
+use quick_xml::{Reader, escape::escape, events::Event};
+use std::{path::Path, time::Duration};
+
+/// XSPF中一个`<track>`的内容
+#[derive(Default)]
+struct XspfTrack {
+    locations: Vec<String>,
+    title: Option<String>,
+    creator: Option<String>,
+    /// 时长(毫秒)
+    duration: Option<u64>,
+}
+
+/// 解析XSPF播放列表
+///
+/// 只读取`<trackList>`下各`<track>`的`<location>`、`<title>`、`<creator>`与`<duration>`,
+/// 有多个`<location>`时取第一个存在的文件
+pub fn parse_xspf(text: &str, base: &Path) -> AnyResult<LoadedPlaylist> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+    let mut loaded = LoadedPlaylist::default();
+    // 从根元素到当前元素的名称
+    let mut elements: Vec<String> = Vec::new();
+    let mut track: Option<XspfTrack> = None;
+    // 当前元素的文本, 可能由多段文本与CDATA组成
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                if name == "track" && elements.last().is_some_and(|parent| parent == "trackList") {
+                    track = Some(XspfTrack::default());
+                }
+                elements.push(name);
+                text.clear();
+            }
+            Event::Text(e) => text.push_str(&e.unescape()?),
+            Event::CData(e) => text.push_str(&String::from_utf8_lossy(&e.into_inner())),
+            Event::End(_) => {
+                let name = elements.pop().unwrap_or_default();
+                let in_track = elements.last().is_some_and(|parent| parent == "track");
+                match (&mut track, name.as_str()) {
+                    (Some(_), "track") if !in_track => {
+                        if let Some(track) = track.take() {
+                            push_track(&mut loaded, track, base);
+                        }
+                    }
+                    (Some(track), field) if in_track => {
+                        let value = text.trim().to_string();
+                        match field {
+                            "location" => track.locations.push(value),
+                            "title" => track.title = Some(value).filter(|v| !v.is_empty()),
+                            "creator" => track.creator = Some(value).filter(|v| !v.is_empty()),
+                            "duration" => track.duration = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(loaded)
+}
+
+/// 解析完一个`<track>`, 位置都不存在时记录第一个位置的原文
+fn push_track(loaded: &mut LoadedPlaylist, track: XspfTrack, base: &Path) {
+    let path = track
+        .locations
+        .iter()
+        .find_map(|location| resolve_location(location, base));
+    let item = match (path, track.locations.into_iter().next()) {
+        (Some(path), _) => PlaylistItem {
+            path,
+            ..Default::default()
+        },
+        (None, Some(location)) => PlaylistItem::unresolved(location),
+        // 没有位置的项直接忽略
+        (None, None) => return,
+    };
+    loaded.items.push(PlaylistItem {
+        title: track.title,
+        artist: track.creator,
+        duration: track.duration.filter(|ms| *ms > 0).map(Duration::from_millis),
+        ..item
+    });
+}
+
+/// 生成XSPF播放列表, 位置为相对于`base`的URI, 没有共同前缀时为`file://`地址
+pub fn write_xspf(items: &[PlaylistItem], base: &Path) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for item in items {
+        let location = match &item.location {
+            Some(location) => location.clone(),
+            None => location_uri(&relative_location(&item.path, base)),
+        };
+        text.push_str("    <track>\n");
+        text.push_str(&format!("      <location>{}</location>\n", escape(location.as_str())));
+        if let Some(title) = &item.title {
+            text.push_str(&format!("      <title>{}</title>\n", escape(title.as_str())));
+        }
+        if let Some(artist) = &item.artist {
+            text.push_str(&format!("      <creator>{}</creator>\n", escape(artist.as_str())));
+        }
+        if let Some(duration) = item.duration {
+            text.push_str(&format!("      <duration>{}</duration>\n", duration.as_millis()));
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+/// 路径转为URI, 绝对路径为`file://`地址
+fn location_uri(location: &str) -> String {
+    let absolute = Path::new(location).is_absolute();
+    // Windows的绝对路径形如`C:\music`, 同样转为`file:///`地址
+    let location = percent_encode(&location.replace('\\', "/"));
+    match (absolute, location.starts_with('/')) {
+        (true, true) => format!("file://{location}"),
+        (true, false) => format!("file:///{location}"),
+        (false, _) => location,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse_first_existing_location() {
+        let dir = TempDir::new("xspf-parse");
+        let base = dir.files(&["a b.mp3"]);
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>ignored</title>
+  <trackList>
+    <track>
+      <location>gone.mp3</location>
+      <location>a%20b.mp3</location>
+      <title><![CDATA[Rock & Roll]]></title>
+      <creator>A &amp; B</creator>
+      <duration>61500</duration>
+    </track>
+    <track><location>missing.mp3</location><location>also.mp3</location></track>
+    <track><title>no location</title></track>
+  </trackList>
+</playlist>"#;
+        let loaded = parse_xspf(text, &base).unwrap();
+        let tracks: Vec<_> = loaded.tracks().collect();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].path, base.join("a b.mp3"));
+        assert_eq!(tracks[0].title.as_deref(), Some("Rock & Roll"));
+        assert_eq!(tracks[0].artist.as_deref(), Some("A & B"));
+        assert_eq!(tracks[0].duration, Some(Duration::from_millis(61500)));
+        // 都不存在时记录第一个位置, 没有位置的项忽略
+        assert_eq!(loaded.missing().collect::<Vec<_>>(), ["missing.mp3"]);
+    }
+
+    #[test]
+    fn write_then_parse_round_trip() {
+        let dir = TempDir::new("xspf-round-trip");
+        let base = dir.files(&["a & b.mp3"]);
+        let outside_dir = TempDir::new("xspf-outside");
+        let outside = outside_dir.files(&["c.mp3"]);
+        let items = vec![
+            PlaylistItem {
+                path: base.join("a & b.mp3"),
+                title: Some("<Title>".to_string()),
+                artist: Some("Artist".to_string()),
+                duration: Some(Duration::from_millis(90_250)),
+                ..Default::default()
+            },
+            PlaylistItem::unresolved("file:///gone/x%20y.mp3".to_string()),
+            PlaylistItem {
+                path: outside.join("c.mp3"),
+                ..Default::default()
+            },
+        ];
+        let text = write_xspf(&items, &base);
+        assert!(text.contains("<location>a%20%26%20b.mp3</location>"), "{text}");
+        assert!(text.contains("<title>&lt;Title&gt;</title>"), "{text}");
+        assert_eq!(parse_xspf(&text, &base).unwrap().items, items);
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{AnyResult, player::PlayCore};
+use crate::{
+    AnyResult, RepeatMode, ShuffleMode, SleepMode,
+    player::PlayCore,
+    view::{update_lyrics_view, update_queue_view},
+};
+use clap::ValueEnum;
 use std::{
     io::{self, Write},
     time::Duration,
@@ -13,7 +18,7 @@ use crossterm::{
 pub fn update_ui(core: &PlayCore) -> AnyResult<()> {
     // 获取当前播放位置
     let current_pos = core.get_pos();
-    let current_lrc = update_lrc(core, current_pos);
+    let lyrics = update_lyrics_view(core, current_pos);
     // 准备字符串
     let information = update_info(core, current_pos.as_secs());
     let progress_line = update_progress_line(core, current_pos.as_secs());
@@ -27,9 +32,32 @@ pub fn update_ui(core: &PlayCore) -> AnyResult<()> {
     // 打印进度条
     print!("{}", progress_line);
     move_and_clear_new_line()?;
-    // 打印歌词
-    print!("🎤 {}", current_lrc.cyan().bold());
+    // 打印歌词面板
+    for line in lyrics {
+        print!("{}", line);
+        move_and_clear_new_line()?;
+    }
+    // 打印书签名称输入框或提示信息
+    if let Some(prompt) = &core.prompt {
+        match prompt.name.is_empty() {
+            true => print!("🔖 书签名称: _{}", format!(" (回车使用\"{}\")", core.default_bookmark_name()).dimmed()),
+            false => print!("🔖 书签名称: {}_", prompt.name.yellow()),
+        }
+    } else if let Some(message) = &core.message {
+        print!("⚠️ {}", message.red());
+    } else if core.is_finished() {
+        print!("⏹ {}", "列表已播完, 按空格键从头播放".yellow());
+    }
     move_and_clear_new_line()?;
+    // 打印待播队列视图
+    if let Some(view) = &core.view {
+        for line in update_queue_view(core, view) {
+            print!("{}", line);
+            move_and_clear_new_line()?;
+        }
+    }
+    // 清除视图关闭后残留的内容
+    execute!(io::stdout(), Clear(ClearType::FromCursorDown))?;
     io::stdout().flush()?;
     Ok(())
 }
@@ -45,58 +73,49 @@ pub fn clear_screen() {
     #[cfg(unix)]
     std::process::Command::new("clear").status().ok();
 }
-/// 更新当前歌词
-fn update_lrc(core: &PlayCore, current_pos: Duration) -> String {
-    // 默认无歌词
-    let mut lrc_to_display = "".to_string();
-    // 查找当前应显示的歌词
-    if let Some(lyrics) = &core.lyrics {
-        // 查找最后一个时间点小于等于当前播放时间的歌词, `rfind` 从后往前找，效率更高
-        if let Some((_time, text)) = lyrics.iter().rfind(|(time, _)| *time <= current_pos) {
-            lrc_to_display = text.clone();
-        }
-    }
-    lrc_to_display
-}
-/// 更新进度条
+/// 更新进度条, 并标出A-B循环的A/B点与书签
 fn update_progress_line(core: &PlayCore, current_pos: u64) -> String {
     // 进度条打印字符长度
     let progress_total_len = 35;
-    // 每个字符对应的时间范围
-    let seconds_per_char = core.src_time / progress_total_len;
+    // 播放位置换算为字符数, 时长未知时不显示进度
+    let to_chars = |secs: u64| match core.src_time {
+        0 => 0,
+        total => (secs * progress_total_len / total).min(progress_total_len),
+    };
     // 当前进度字符长度
-    let current_progress = match current_pos / seconds_per_char {
-        result if result >= 1 => {
-            if result <= progress_total_len {
-                result
-            } else {
-                progress_total_len
-            }
-        }
-        _ => 0,
+    let current_progress = to_chars(current_pos);
+    // A/B点所在的字符位置
+    let marker = |point: Option<Duration>| {
+        point.map(|p| to_chars(p.as_secs()).min(progress_total_len - 1))
     };
-    // 进度条字符串
-    match progress_total_len - current_progress {
-        // 剩余进度字符长度
-        remaining_progress if remaining_progress >= 1 => {
-            if current_progress >= 1 {
-                format!(
-                    "<>{}{}<>",
-                    "#".repeat(current_progress as usize).blue(),
-                    "-".repeat(remaining_progress as usize)
-                )
+    let (a, b) = (marker(core.ab_loop.a), marker(core.ab_loop.b));
+    let bookmarks: Vec<u64> = core
+        .current_bookmarks()
+        .iter()
+        .filter_map(|bookmark| marker(Some(bookmark.pos)))
+        .collect();
+    let bar: String = (0..progress_total_len)
+        .map(|i| {
+            let ch = if Some(i) == a {
+                "A".yellow().bold()
+            } else if Some(i) == b {
+                "B".yellow().bold()
+            } else if bookmarks.contains(&i) {
+                "|".magenta().bold()
+            } else if i < current_progress {
+                "#".blue()
             } else {
-                format!(
-                    "{}{}<>",
-                    "<>".blue(),
-                    "-".repeat(remaining_progress as usize)
-                )
-            }
-        }
-        _ => {
-            format!("<>{}<>", "#".repeat(current_progress as usize).blue())
-        }
-    }
+                "-".normal()
+            };
+            ch.to_string()
+        })
+        .collect();
+    // 尚未开始时左端高亮
+    let head = match current_progress {
+        0 => "<>".blue(),
+        _ => "<>".normal(),
+    };
+    format!("{}{}<>", head, bar)
 }
 
 /// 更新歌曲信息
@@ -104,13 +123,60 @@ fn update_info(core: &PlayCore, current_pos: u64) -> String {
     let minutes = current_pos / 60;
     let seconds = current_pos % 60;
     let now_time = format!("{:02}:{:02}", minutes, seconds);
+    let volume = if core.muted {
+        "🔇".to_string()
+    } else {
+        format!("🔊{}%", (core.volume * 100.0).round())
+    };
+    // 非原速时显示播放速度
+    let speed = match core.speed.get() {
+        speed if (speed - 1.0).abs() > f32::EPSILON => format!(" ⏩{speed:.1}x"),
+        _ => String::new(),
+    };
+    // 非flat时显示均衡器预设
+    let eq = match core.eq_preset() {
+        Some(preset) if !preset.is_flat() => format!(" 🎚{}", preset.name),
+        _ => String::new(),
+    };
+    // 随机播放模式
+    let shuffle = match core.shuffle.mode {
+        ShuffleMode::Off => String::new(),
+        mode => format!(" 🔀{}", mode.to_possible_value().unwrap().get_name()),
+    };
+    // 循环模式
+    let repeat = match core.repeat {
+        RepeatMode::Off => " ➡️once",
+        RepeatMode::All => " 🔁all",
+        RepeatMode::One => " 🔂one",
+    };
+    // 定时停止的倒计时, 按曲目数定时时显示剩余曲目数
+    let sleep = match (core.sleep_remaining(), core.sleep.map(|s| s.mode)) {
+        (Some(remaining), _) => {
+            let secs = remaining.as_secs();
+            format!(" 💤{:02}:{:02}", secs / 60, secs % 60)
+        }
+        (None, Some(SleepMode::Tracks(n))) => format!(" 💤{}首", n + 1),
+        _ => String::new(),
+    };
+    // 当前曲目的书签数
+    let bookmarks = match core.current_bookmarks().len() {
+        0 => String::new(),
+        n => format!(" 🔖{n}"),
+    };
     format!(
-        "📀 {}/{} 🎧{} ⏳{}/{}",
-        core.current_audio_idx.to_string().blue(),
-        core.audio_total.to_string().yellow(),
-        core.file_name.blue(),
+        "📀 {}/{} 🎧{} ⏳{}/{} {}{}{}{}{}{}{}",
+        (core.playlist.current() + 1).to_string().blue(),
+        core.playlist.len().to_string().yellow(),
+        core.metadata.format(&core.title_format).blue(),
         now_time.blue(),
-        core.total_time.green()
+        core.total_time.green(),
+        volume.yellow(),
+        speed.magenta(),
+        eq.cyan(),
+        shuffle.green(),
+        repeat.green(),
+        sleep.red(),
+        bookmarks.magenta()
     )
 }
 
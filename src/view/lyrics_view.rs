@@ -0,0 +1,177 @@
+use crate::{LyricLine, player::PlayCore};
+use colored::Colorize;
+use crossterm::terminal;
+use std::{cmp::Ordering, time::Duration};
+use unicode_width::UnicodeWidthChar;
+
+/// 无法获取终端宽度(或为0)时假定的列数
+const FALLBACK_WIDTH: usize = 80;
+/// 行首标记占用的列数, 当前行为`🎤 `, 其余行为空格
+const PREFIX_WIDTH: usize = 3;
+/// 切换到下一行时, 面板每滚动一行所用的时间
+const SCROLL_STEP: Duration = Duration::from_millis(100);
+
+/// 渲染滚动歌词面板, 每个元素为一行
+///
+/// 当前行高亮显示并按演唱进度逐字着色, 之前显示`before`行、之后显示`after`行. 两侧都按较多
+/// 的一侧留出行数, 当前行始终位于面板正中, 面板高度不随歌词进度跳动. 切换到下一行时逐行
+/// 滚动到新位置. 过长的歌词按终端显示宽度折行, 中日韩文字计为两列
+pub fn update_lyrics_view(core: &PlayCore, current_pos: Duration) -> Vec<String> {
+    let Some(lyrics) = core.lyrics.as_ref().filter(|lyrics| !lyrics.is_empty()) else {
+        return vec!["🎤 ".to_string()];
+    };
+    // 最后一列不写入, 避免终端自动换行
+    let width = terminal::size()
+        .ok()
+        .filter(|(cols, _)| *cols > 0)
+        .map_or(FALLBACK_WIDTH, |(cols, _)| cols as usize)
+        .saturating_sub(PREFIX_WIDTH + 1)
+        .max(1);
+    // 查找最后一个时间点小于等于当前播放时间的歌词, 从后往前找，效率更高
+    let current = lyrics.iter().rposition(|line| line.time <= current_pos);
+    let (before, after) = match current {
+        Some(idx) => (&lyrics[..idx], &lyrics[idx + 1..]),
+        None => (&lyrics[..0], &lyrics[..]),
+    };
+    // 全部歌词折行后的各行, 面板以其中的`center`行为中心
+    let mut rows: Vec<String> = before
+        .iter()
+        .flat_map(|line| wrap(&line.text, width))
+        .map(|row| format!("   {}", row.dimmed()))
+        .collect();
+    let center = match current {
+        Some(idx) => {
+            let start = rows.len();
+            // 从上一行所在的位置滚动过来
+            let from = before
+                .last()
+                .map_or(start, |line| start - wrap(&line.text, width).len());
+            let next = lyrics.get(idx + 1).map(|next| next.time);
+            rows.extend(current_line(&lyrics[idx], current_pos, next, width));
+            scroll_row(from, start, current_pos - lyrics[idx].time)
+        }
+        None => {
+            rows.push("🎤 ".to_string());
+            0
+        }
+    };
+    rows.extend(
+        after
+            .iter()
+            .flat_map(|line| wrap(&line.text, width))
+            .map(|row| format!("   {}", row)),
+    );
+    panel(&rows, center, core.lyrics_before, core.lyrics_after)
+}
+
+/// 当前行开始`elapsed`后面板中心所在的行: 从`from`起每隔`SCROLL_STEP`前进一行, 直到`to`
+fn scroll_row(from: usize, to: usize, elapsed: Duration) -> usize {
+    let steps = elapsed.as_millis() / SCROLL_STEP.as_millis();
+    to.min(from.saturating_add(steps as usize))
+}
+
+/// 以`rows[center]`为中心截取面板, 之前显示`before`行、之后显示`after`行,
+/// 两侧都补足到`before`与`after`中较大的行数
+fn panel(rows: &[String], center: usize, before: usize, after: usize) -> Vec<String> {
+    let half = before.max(after);
+    (0..half * 2 + 1)
+        .map(|i| {
+            let visible = match i.cmp(&half) {
+                Ordering::Less => half - i <= before,
+                _ => i - half <= after,
+            };
+            (center + i)
+                .checked_sub(half)
+                .and_then(|row| rows.get(row))
+                .filter(|_| visible)
+                .cloned()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// 渲染当前行, 有逐字时间时已唱的部分高亮、未唱的部分变暗
+fn current_line(
+    line: &LyricLine,
+    pos: Duration,
+    next: Option<Duration>,
+    width: usize,
+) -> Vec<String> {
+    let sung = match line.words.is_empty() {
+        true => usize::MAX,
+        false => line.sung_chars(pos, next),
+    };
+    let mut offset = 0;
+    wrap(&line.text, width)
+        .into_iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let split = row
+                .char_indices()
+                .nth(sung.saturating_sub(offset))
+                .map_or(row.len(), |(at, _)| at);
+            offset += row.chars().count();
+            let (done, rest) = row.split_at(split);
+            let prefix = if i == 0 { "🎤 " } else { "   " };
+            format!("{prefix}{}{}", done.cyan().bold(), rest.dimmed())
+        })
+        .collect()
+}
+
+/// 按显示宽度折行, 每行不超过`width`列
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut used = 0;
+    for ch in text.chars() {
+        let ch_width = ch.width().unwrap_or(0);
+        if used + ch_width > width && used > 0 {
+            rows.push(String::new());
+            used = 0;
+        }
+        if let Some(row) = rows.last_mut() {
+            row.push(ch);
+        }
+        used += ch_width;
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_counts_cjk_as_two_columns() {
+        assert_eq!(wrap("你好世界", 5), ["你好", "世界"]);
+        assert_eq!(wrap("ab你好c", 4), ["ab你", "好c"]);
+        assert_eq!(wrap("hello", 5), ["hello"]);
+        assert_eq!(wrap("", 5), [""]);
+        // 宽度不足一个字时每行一个字
+        assert_eq!(wrap("你好", 1), ["你", "好"]);
+    }
+
+    #[test]
+    fn current_row_stays_centered() {
+        let rows: Vec<String> = "abcdefg".chars().map(String::from).collect();
+        assert_eq!(panel(&rows, 3, 1, 3), ["", "", "c", "d", "e", "f", "g"]);
+        assert_eq!(panel(&rows, 3, 3, 1), ["a", "b", "c", "d", "e", "", ""]);
+        assert_eq!(panel(&rows, 3, 2, 2), ["b", "c", "d", "e", "f"]);
+        assert_eq!(panel(&rows, 3, 0, 0), ["d"]);
+    }
+
+    #[test]
+    fn panel_pads_beyond_the_lyrics() {
+        let rows: Vec<String> = "abc".chars().map(String::from).collect();
+        assert_eq!(panel(&rows, 0, 2, 2), ["", "", "a", "b", "c"]);
+        assert_eq!(panel(&rows, 2, 1, 2), ["", "b", "c", "", ""]);
+    }
+
+    #[test]
+    fn scrolls_one_row_per_step() {
+        assert_eq!(scroll_row(2, 5, Duration::ZERO), 2);
+        assert_eq!(scroll_row(2, 5, SCROLL_STEP), 3);
+        assert_eq!(scroll_row(2, 5, SCROLL_STEP * 5 / 2), 4);
+        assert_eq!(scroll_row(2, 5, Duration::from_secs(10)), 5);
+        assert_eq!(scroll_row(5, 5, Duration::ZERO), 5);
+    }
+}
@@ -1,2 +1,6 @@
 mod cli_ui;
-pub use cli_ui::*;
\ No newline at end of file
+mod lyrics_view;
+mod queue_view;
+pub use cli_ui::*;
+pub use lyrics_view::*;
+pub use queue_view::*;
@@ -0,0 +1,121 @@
+use crate::player::PlayCore;
+use colored::Colorize;
+use std::ops::Range;
+
+/// 播放列表一栏显示的行数
+const PLAYLIST_ROWS: usize = 7;
+/// 队列一栏显示的行数
+const QUEUE_ROWS: usize = 5;
+/// 标题最多显示的字符数, 避免折行打乱界面
+const TITLE_CHARS: usize = 50;
+
+/// 待播队列视图中获得焦点的一栏
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pane {
+    #[default]
+    Playlist,
+    Queue,
+}
+
+/// 待播队列视图: 上栏为播放列表, 下栏为待播队列, 各有一个光标
+#[derive(Clone, Debug, Default)]
+pub struct QueueView {
+    pub pane: Pane,
+    /// 播放列表一栏的光标位置
+    pub cursor: usize,
+    /// 队列一栏的光标位置
+    pub queue_cursor: usize,
+}
+
+impl QueueView {
+    /// 新建视图, 播放列表的光标位于`cursor`
+    pub fn new(cursor: usize) -> Self {
+        Self {
+            cursor,
+            ..Default::default()
+        }
+    }
+    /// 切换焦点
+    pub fn switch_pane(&mut self) {
+        self.pane = match self.pane {
+            Pane::Playlist => Pane::Queue,
+            Pane::Queue => Pane::Playlist,
+        };
+    }
+    /// 移动焦点所在一栏的光标, 限制在`len`项之内
+    pub fn move_cursor(&mut self, up: bool, len: usize) {
+        let cursor = match self.pane {
+            Pane::Playlist => &mut self.cursor,
+            Pane::Queue => &mut self.queue_cursor,
+        };
+        *cursor = match up {
+            true => cursor.saturating_sub(1),
+            false => (*cursor + 1).min(len.saturating_sub(1)),
+        };
+    }
+    /// 列表或队列变短后, 光标保持在范围内
+    pub fn clamp(&mut self, playlist_len: usize, queue_len: usize) {
+        self.cursor = self.cursor.min(playlist_len.saturating_sub(1));
+        self.queue_cursor = self.queue_cursor.min(queue_len.saturating_sub(1));
+    }
+}
+
+/// 渲染待播队列视图, 每个元素为一行
+pub fn update_queue_view(core: &PlayCore, view: &QueueView) -> Vec<String> {
+    let mut lines = Vec::new();
+    let header = |text: &str, focused: bool| match focused {
+        true => text.yellow().bold().to_string(),
+        false => text.normal().to_string(),
+    };
+    let row = |text: String, selected: bool, focused: bool| match (selected, focused) {
+        (true, true) => format!("> {}", text).reversed().to_string(),
+        (true, false) => format!("> {}", text),
+        _ => format!("  {}", text),
+    };
+    let focused = view.pane == Pane::Playlist;
+    lines.push(header(
+        "📜 播放列表 [Tab]切换 [n]下一首播放 [q]加入队列 [Enter]播放",
+        focused,
+    ));
+    let playlist = &core.playlist;
+    for idx in window(view.cursor, playlist.len(), PLAYLIST_ROWS) {
+        let Some(entry) = playlist.get(idx) else {
+            continue;
+        };
+        let playing = if idx == playlist.current() { "▶" } else { " " };
+        let text = format!("{playing}{:>4}. {}", idx + 1, truncate(&core.entry_title(entry)));
+        lines.push(row(text, idx == view.cursor, focused));
+    }
+    let focused = view.pane == Pane::Queue;
+    lines.push(header(
+        &format!("📋 待播队列({}) [Shift+↑/↓]移动 [Del]移除 [Enter]立即播放", core.queue.len()),
+        focused,
+    ));
+    if core.queue.is_empty() {
+        lines.push("  (空)".dimmed().to_string());
+    }
+    let ids: Vec<u64> = core.queue.iter().copied().collect();
+    for idx in window(view.queue_cursor, ids.len(), QUEUE_ROWS) {
+        let title = playlist
+            .position_of_id(ids[idx])
+            .and_then(|pos| playlist.get(pos))
+            .map_or_else(String::new, |entry| core.entry_title(entry));
+        let text = format!("{:>5}. {}", idx + 1, truncate(&title));
+        lines.push(row(text, idx == view.queue_cursor, focused));
+    }
+    lines
+}
+
+/// 以光标为中心、最多`rows`行的显示范围
+fn window(cursor: usize, len: usize, rows: usize) -> Range<usize> {
+    let start = cursor.saturating_sub(rows / 2).min(len.saturating_sub(rows));
+    start..(start + rows).min(len)
+}
+
+/// 截断过长的标题
+fn truncate(title: &str) -> String {
+    match title.chars().nth(TITLE_CHARS) {
+        Some(_) => format!("{}…", title.chars().take(TITLE_CHARS).collect::<String>()),
+        None => title.to_string(),
+    }
+}